@@ -1,29 +1,187 @@
 use core::str;
-use std::{
-    collections::HashMap,
-    fs::{self},
-    path::Path,
-};
-
-#[derive(Debug)]
+use std::{collections::HashSet, fmt};
+
+mod borrowed;
+pub mod bytes;
+mod de;
+mod dict;
+mod json;
+mod path;
+mod pretty;
+mod ser;
+mod stream;
+
+pub use borrowed::{BencodeRef, BencodeRefType};
+pub use de::{from_bytes, from_value};
+pub use dict::BencodeDict;
+pub use path::PathError;
+pub use ser::{to_bytes, to_value};
+pub use stream::{BencodeReader, StreamDecoder};
+
+#[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub enum BencodeType {
-    Int(i32),
+    Int(i64),
+    /// An integer outside of the `i64` range, kept as its original digits
+    /// (including a leading `-`). Only produced with `DecodeOptions::big_ints`.
+    BigInt(String),
     Str(Vec<u8>),
     List(Vec<BencodeType>),
-    Dict(HashMap<String, BencodeType>),
+    Dict(BencodeDict),
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct BencodeError {
-    message: String,
+/// Reasons why bencoded input was rejected. Every parser error carries the
+/// byte offset into the input at which the problem was detected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BencodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEof { offset: usize },
+    /// A byte that cannot start or continue a value at this position.
+    UnexpectedByte { offset: usize, byte: u8 },
+    /// The part between `i` and `e` is not a base ten integer that fits into
+    /// an `i64` (unless `DecodeOptions::big_ints` is set).
+    InvalidInteger { offset: usize },
+    /// An integer or string length written with a superfluous leading zero.
+    LeadingZero { offset: usize },
+    /// `i-0e`, which the specification forbids.
+    NegativeZero { offset: usize },
+    /// A string length prefix that does not fit into memory.
+    InvalidLength { offset: usize },
+    /// A dictionary key that is not a string.
+    NonStringKey { offset: usize },
+    /// A dictionary key that does not sort after the previous one (strict mode).
+    UnsortedKeys { offset: usize },
+    /// A dictionary key that appears twice.
+    DuplicateKey { offset: usize },
+    /// Input left over after the top level value.
+    TrailingData { offset: usize },
+    /// Lists and dictionaries nested deeper than `DecodeOptions::max_depth`.
+    NestingTooDeep { offset: usize },
+    /// A string longer than `DecodeOptions::max_string_len`.
+    StringTooLong { offset: usize },
+    /// More values than `DecodeOptions::max_elements`.
+    TooManyElements { offset: usize },
+    /// Input longer than `DecodeOptions::max_total_size`.
+    InputTooLarge { offset: usize },
+    /// An error raised while serializing or deserializing with serde.
+    Message(String),
 }
 
 impl BencodeError {
     fn new(msg: &str) -> Self {
-        BencodeError {
-            message: String::from(msg),
+        BencodeError::Message(String::from(msg))
+    }
+
+    /// Byte offset into the input at which the error was detected, if the
+    /// error came from the parser.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            BencodeError::UnexpectedEof { offset }
+            | BencodeError::UnexpectedByte { offset, .. }
+            | BencodeError::InvalidInteger { offset }
+            | BencodeError::LeadingZero { offset }
+            | BencodeError::NegativeZero { offset }
+            | BencodeError::InvalidLength { offset }
+            | BencodeError::NonStringKey { offset }
+            | BencodeError::UnsortedKeys { offset }
+            | BencodeError::DuplicateKey { offset }
+            | BencodeError::TrailingData { offset }
+            | BencodeError::NestingTooDeep { offset }
+            | BencodeError::StringTooLong { offset }
+            | BencodeError::TooManyElements { offset }
+            | BencodeError::InputTooLarge { offset } => Some(*offset),
+            BencodeError::Message(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for BencodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            BencodeError::UnexpectedEof { .. } => "unexpected end of input",
+            BencodeError::UnexpectedByte { byte, offset } => {
+                return write!(f, "unexpected byte 0x{:02x} at offset {}", byte, offset)
+            }
+            BencodeError::InvalidInteger { .. } => "invalid integer",
+            BencodeError::LeadingZero { .. } => "number with a leading zero",
+            BencodeError::NegativeZero { .. } => "negative zero",
+            BencodeError::InvalidLength { .. } => "invalid string length",
+            BencodeError::NonStringKey { .. } => "dictionary key is not a string",
+            BencodeError::UnsortedKeys { .. } => "dictionary keys are not sorted",
+            BencodeError::DuplicateKey { .. } => "duplicate dictionary key",
+            BencodeError::TrailingData { .. } => "trailing data after the value",
+            BencodeError::NestingTooDeep { .. } => "nesting too deep",
+            BencodeError::StringTooLong { .. } => "string too long",
+            BencodeError::TooManyElements { .. } => "too many elements",
+            BencodeError::InputTooLarge { .. } => "input too large",
+            BencodeError::Message(msg) => return f.write_str(msg),
+        };
+        match self.offset() {
+            Some(offset) => write!(f, "{} at offset {}", msg, offset),
+            None => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for BencodeError {}
+
+impl serde::ser::Error for BencodeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        BencodeError::new(&msg.to_string())
+    }
+}
+
+impl serde::de::Error for BencodeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        BencodeError::new(&msg.to_string())
+    }
+}
+
+/// Knobs for `Bencode::from_u8_with`.
+///
+/// The default limits are meant for untrusted data from peers and trackers;
+/// use `DecodeOptions::trusted` for input such as local .torrent files.
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Reject input that is not in canonical form, i.e. dictionaries whose
+    /// keys are not sorted.
+    pub strict: bool,
+    /// Keep integers that do not fit into an `i64` as `BencodeType::BigInt`
+    /// instead of rejecting them.
+    pub big_ints: bool,
+    /// How deeply lists and dictionaries may nest. The parser recurses once
+    /// per level, so this also bounds its stack usage.
+    pub max_depth: usize,
+    /// Longest accepted string, in bytes.
+    pub max_string_len: usize,
+    /// Most values (of any type, including nested ones) in one input.
+    pub max_elements: usize,
+    /// Longest accepted input, in bytes.
+    pub max_total_size: usize,
+}
+
+impl DecodeOptions {
+    /// Options without size limits, for input that does not come from the
+    /// network. Nesting stays limited to keep the stack safe.
+    pub fn trusted() -> Self {
+        DecodeOptions {
+            max_string_len: usize::MAX,
+            max_elements: usize::MAX,
+            max_total_size: usize::MAX,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            strict: false,
+            big_ints: false,
+            max_depth: 128,
+            max_string_len: 16 * 1024 * 1024,
+            max_elements: 1024 * 1024,
+            max_total_size: 32 * 1024 * 1024,
         }
     }
 }
@@ -31,40 +189,28 @@ impl BencodeError {
 #[derive(Debug)]
 pub struct Bencode<'a> {
     pub node: BencodeType,
-    len: usize,
+    pub len: usize,
     pub slice: &'a [u8],
 }
 
 impl<'a> Bencode<'a> {
     pub fn from_u8(bencoded_input: &'a [u8]) -> Result<Self, BencodeError> {
-        let first_char = bencoded_input.first().unwrap();
-        let first_char = *first_char as char;
-        let res = match first_char {
-            'i' => Bencode::parse_int(bencoded_input),
-            'l' => Bencode::parse_list(bencoded_input),
-            'd' => Bencode::parse_dict(bencoded_input),
-            _ if first_char.is_numeric() => Bencode::parse_str(bencoded_input),
-            _ => Err(BencodeError::new(
-                "bencoded_input should be a properly formatted BEncode string",
-            )),
-        };
+        Bencode::from_u8_with(bencoded_input, &DecodeOptions::default())
+    }
 
-        res
-    }
-
-    // pub fn from_str(bencoded_input: &str) -> Result<Self, BencodeError> {
-    //     unimplemented!();
-    //     let first_char = bencoded_input.chars().nth(0).unwrap();
-    //     match first_char {
-    //         'i' => Bencode::parse_int(bencoded_input),
-    //         'l' => Bencode::parse_list(bencoded_input),
-    //         'd' => Bencode::parse_dict(bencoded_input),
-    //         _ if first_char.is_numeric() => Bencode::parse_str(bencoded_input),
-    //         _ => Err(BencodeError::new(
-    //             "bencoded_input should be a properly formatted BEncode string",
-    //         )),
-    //     }
-    // }
+    /// Parses a single bencoded value spanning the whole of `bencoded_input`.
+    pub fn from_u8_with(
+        bencoded_input: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Self, BencodeError> {
+        let value = BencodeRef::from_u8_with(bencoded_input, options)?;
+
+        Ok(Bencode {
+            node: value.to_bencode_type(),
+            len: value.slice.len(),
+            slice: value.slice,
+        })
+    }
 
     // pub fn from_file(file_path: &Path) -> Result<Self, BencodeError> {
     //     let contents = if let Ok(str) = fs::read(file_path) {
@@ -75,120 +221,291 @@ impl<'a> Bencode<'a> {
     //
     //     Bencode::from_u8(&contents)
     // }
+}
 
-    fn parse_int(bencoded_str: &'a [u8]) -> Result<Self, BencodeError> {
-        let mut parts = bencoded_str[1..].split(|b| *b as char == 'e');
-        // TODO
-        let part = str::from_utf8(parts.next().unwrap()).unwrap();
-        let len = part.len() + 2;
+struct Parser<'a, 'o> {
+    input: &'a [u8],
+    pos: usize,
+    depth: usize,
+    elements: usize,
+    options: &'o DecodeOptions,
+}
 
-        match part.parse::<i32>() {
-            Ok(int) => Ok(Bencode {
-                node: BencodeType::Int(int),
-                slice: &bencoded_str[..len],
-                len,
-            }),
-            Err(_) => Err(BencodeError::new(
-                "part between 'i' and 'e' should be a properly formatted integer",
-            )),
-        }
+impl<'a, 'o> Parser<'a, 'o> {
+    fn peek(&self) -> Result<u8, BencodeError> {
+        self.input
+            .get(self.pos)
+            .copied()
+            .ok_or(BencodeError::UnexpectedEof { offset: self.pos })
     }
 
-    fn parse_str(bencoded_str: &'a [u8]) -> Result<Self, BencodeError> {
-        let first_part = if let Some(first_part) = bencoded_str.split(|b| *b as char == ':').next()
-        {
-            first_part
-        } else {
-            return Err(BencodeError::new("string element should contain a ':'"));
-        };
+    fn parse_value(&mut self) -> Result<BencodeRef<'a>, BencodeError> {
+        let start = self.pos;
+        self.elements += 1;
+        if self.elements > self.options.max_elements {
+            return Err(BencodeError::TooManyElements { offset: start });
+        }
 
-        let first_part = if let Ok(first_part) = str::from_utf8(first_part) {
-            first_part
-        } else {
-            return Err(BencodeError::new("string element should contain a ':'"));
+        let node = match self.peek()? {
+            b'i' => self.parse_int()?,
+            b'l' => self.parse_list()?,
+            b'd' => self.parse_dict()?,
+            b'0'..=b'9' => BencodeRefType::Str(self.parse_str()?),
+            byte => {
+                return Err(BencodeError::UnexpectedByte {
+                    offset: self.pos,
+                    byte,
+                })
+            }
         };
 
-        let len_second_part = if let Ok(len_second_part) = first_part.parse::<usize>() {
-            len_second_part
-        } else {
-            return Err(BencodeError::new(
-                "the part before ':' should be a properly formatted integer",
-            ));
-        };
+        Ok(BencodeRef {
+            node,
+            slice: &self.input[start..self.pos],
+        })
+    }
+
+    /// Consumes ASCII digits up to `terminator` and returns them together with
+    /// the offset of the first one; the terminator itself is consumed too.
+    fn take_digits(&mut self, terminator: u8) -> Result<(&'a [u8], usize), BencodeError> {
+        let start = self.pos;
+        loop {
+            match self.peek()? {
+                byte if byte == terminator => break,
+                b'0'..=b'9' => self.pos += 1,
+                b'-' if self.pos == start && terminator == b'e' => self.pos += 1,
+                byte => {
+                    return Err(BencodeError::UnexpectedByte {
+                        offset: self.pos,
+                        byte,
+                    })
+                }
+            }
+        }
+        let digits = &self.input[start..self.pos];
+        self.pos += 1;
+        Ok((digits, start))
+    }
 
-        let str = &bencoded_str[first_part.len() + 1..first_part.len() + 1 + len_second_part];
-        let len = first_part.len() + 1 + len_second_part;
+    fn parse_int(&mut self) -> Result<BencodeRefType<'a>, BencodeError> {
+        // skip the 'i' int start char
+        self.pos += 1;
+        let (digits, offset) = self.take_digits(b'e')?;
 
-        Ok(Bencode {
-            node: BencodeType::Str(str.to_vec()),
-            len,
-            slice: &bencoded_str[..len],
-        })
+        let magnitude = digits.strip_prefix(b"-").unwrap_or(digits);
+        if magnitude.is_empty() {
+            return Err(BencodeError::InvalidInteger { offset });
+        }
+        if magnitude == b"0" && digits.len() == 2 {
+            return Err(BencodeError::NegativeZero { offset });
+        }
+        if magnitude[0] == b'0' && magnitude.len() > 1 {
+            return Err(BencodeError::LeadingZero { offset });
+        }
+
+        // only ASCII digits and '-' made it through take_digits
+        let digits = str::from_utf8(digits).map_err(|_| BencodeError::InvalidInteger { offset })?;
+        match digits.parse::<i64>() {
+            Ok(int) => Ok(BencodeRefType::Int(int)),
+            Err(_) if self.options.big_ints => Ok(BencodeRefType::BigInt(digits)),
+            Err(_) => Err(BencodeError::InvalidInteger { offset }),
+        }
+    }
+
+    fn parse_str(&mut self) -> Result<&'a [u8], BencodeError> {
+        let (digits, offset) = self.take_digits(b':')?;
+        if digits[0] == b'0' && digits.len() > 1 {
+            return Err(BencodeError::LeadingZero { offset });
+        }
+
+        let len = str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| digits.parse::<usize>().ok())
+            .ok_or(BencodeError::InvalidLength { offset })?;
+        if len > self.options.max_string_len {
+            return Err(BencodeError::StringTooLong { offset });
+        }
+        let end = self
+            .pos
+            .checked_add(len)
+            .ok_or(BencodeError::InvalidLength { offset })?;
+        if end > self.input.len() {
+            return Err(BencodeError::UnexpectedEof {
+                offset: self.input.len(),
+            });
+        }
+
+        let str = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(str)
     }
 
-    fn parse_list(bencoded_str: &'a [u8]) -> Result<Self, BencodeError> {
-        let mut chars = bencoded_str.iter();
-        let mut result: Vec<BencodeType> = vec![];
+    fn enter(&mut self) -> Result<(), BencodeError> {
+        if self.depth >= self.options.max_depth {
+            return Err(BencodeError::NestingTooDeep { offset: self.pos });
+        }
+        self.depth += 1;
+        // skip the 'l' / 'd' start char
+        self.pos += 1;
+        Ok(())
+    }
 
-        // skip 0th because of the 'l' list start char
-        let mut remaining_bencoded_str = &bencoded_str[1..];
+    fn parse_list(&mut self) -> Result<BencodeRefType<'a>, BencodeError> {
+        self.enter()?;
+        let mut result: Vec<BencodeRef<'a>> = vec![];
 
-        let mut chh = chars.nth(1);
+        while self.peek()? != b'e' {
+            result.push(self.parse_value()?);
+        }
+        self.pos += 1;
+        self.depth -= 1;
+
+        Ok(BencodeRefType::List(result))
+    }
 
-        while let Some(ch) = chh {
-            if *ch as char == 'e' {
-                break;
+    fn parse_dict(&mut self) -> Result<BencodeRefType<'a>, BencodeError> {
+        self.enter()?;
+        let mut result: Vec<(&'a [u8], BencodeRef<'a>)> = vec![];
+        let mut prev_key: Option<&'a [u8]> = None;
+        // only needed for duplicate detection once the keys are out of order
+        let mut seen: Option<HashSet<&'a [u8]>> = None;
+
+        while self.peek()? != b'e' {
+            let offset = self.pos;
+            let key = match self.peek()? {
+                b'0'..=b'9' => self.parse_str()?,
+                _ => return Err(BencodeError::NonStringKey { offset }),
+            };
+
+            let duplicate = match (&mut seen, prev_key) {
+                (Some(seen), _) => !seen.insert(key),
+                (None, Some(prev_key)) if key <= prev_key => {
+                    if key < prev_key && self.options.strict {
+                        return Err(BencodeError::UnsortedKeys { offset });
+                    }
+                    let keys = seen.insert(result.iter().map(|(k, _)| *k).collect());
+                    !keys.insert(key)
+                }
+                _ => false,
+            };
+            if duplicate {
+                return Err(BencodeError::DuplicateKey { offset });
             }
-            let b = Bencode::from_u8(remaining_bencoded_str)?;
-            remaining_bencoded_str = &remaining_bencoded_str[b.len..];
-            result.push(b.node);
-            chh = chars.nth(b.len - 1);
+            prev_key = Some(key);
+
+            let val = self.parse_value()?;
+            result.push((key, val));
         }
-        let len = bencoded_str.len() - remaining_bencoded_str.len() + 1;
+        self.pos += 1;
+        self.depth -= 1;
 
-        Ok(Bencode {
-            node: BencodeType::List(result),
-            len,
-            slice: &bencoded_str[..len],
-        })
+        Ok(BencodeRefType::Dict(result))
     }
+}
 
-    fn parse_dict(bencoded_str: &'a [u8]) -> Result<Self, BencodeError> {
-        let mut chars = bencoded_str.iter();
-        let mut result: HashMap<String, BencodeType> = HashMap::new();
+impl BencodeType {
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            BencodeType::Int(int) => Some(*int),
+            _ => None,
+        }
+    }
 
-        // skip 0th because of the 'd' dict start char
-        let mut remaining_bencoded_str = &bencoded_str[1..];
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            BencodeType::Str(str) => Some(str),
+            _ => None,
+        }
+    }
 
-        let mut chh = chars.nth(1);
+    /// The string, if it is one and is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|str| str::from_utf8(str).ok())
+    }
 
-        while let Some(ch) = chh {
-            if *ch as char == 'e' {
-                break;
-            }
+    pub fn as_list(&self) -> Option<&[BencodeType]> {
+        match self {
+            BencodeType::List(list) => Some(list),
+            _ => None,
+        }
+    }
 
-            let key = Bencode::from_u8(remaining_bencoded_str)?;
-            remaining_bencoded_str = &remaining_bencoded_str[key.len..];
+    pub fn as_dict(&self) -> Option<&BencodeDict> {
+        match self {
+            BencodeType::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Looks up `key` if the value is a dictionary.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&BencodeType> {
+        self.as_dict().and_then(|dict| dict.get(key))
+    }
 
-            let val = Bencode::from_u8(remaining_bencoded_str)?;
-            remaining_bencoded_str = &remaining_bencoded_str[val.len..];
+    /// Encodes the value into its canonical bencoded form: dictionary keys are
+    /// sorted by their raw bytes and integers are written without leading zeros.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    /// Appends the canonical bencoded form of the value to `out`.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        self.write(out, true)
+    }
 
-            if let BencodeType::Str(str) = key.node {
-                result.insert(String::from_utf8(str).unwrap(), val.node);
+    /// Encodes the value keeping dictionary entries in their stored order, so
+    /// parsed input is reproduced byte for byte even if it was not canonical.
+    pub fn encode_preserving_order(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out, false);
+        out
+    }
+
+    fn write(&self, out: &mut Vec<u8>, sort_keys: bool) {
+        match self {
+            BencodeType::Int(int) => {
+                out.push(b'i');
+                out.extend(int.to_string().as_bytes());
+                out.push(b'e');
+            }
+            BencodeType::BigInt(digits) => {
+                out.push(b'i');
+                out.extend(digits.as_bytes());
+                out.push(b'e');
+            }
+            BencodeType::Str(str) => encode_str(str, out),
+            BencodeType::List(list) => {
+                out.push(b'l');
+                for item in list {
+                    item.write(out, sort_keys);
+                }
+                out.push(b'e');
+            }
+            BencodeType::Dict(dict) => {
+                let mut entries: Vec<(&[u8], &BencodeType)> = dict.iter().collect();
+                if sort_keys {
+                    entries.sort_by_key(|(key, _)| *key);
+                }
+
+                out.push(b'd');
+                for (key, val) in entries {
+                    encode_str(key, out);
+                    val.write(out, sort_keys);
+                }
+                out.push(b'e');
             }
-            chh = chars.nth(key.len + val.len - 1);
         }
-        let len = bencoded_str.len() - remaining_bencoded_str.len() + 1;
-
-        Ok(Bencode {
-            node: BencodeType::Dict(result),
-            // 1 is the ':' delimiter
-            len,
-            slice: &bencoded_str[..len],
-        })
     }
 }
 
+fn encode_str(str: &[u8], out: &mut Vec<u8>) {
+    out.extend(str.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend(str);
+}
+
 // struct BencodeIterator<'a> {
 //     bencode: &'a Bencode,
 //     curr: Option<&'a Bencode>,
@@ -212,7 +529,7 @@ impl<'a> Bencode<'a> {
 
 #[cfg(test)]
 mod test {
-    use crate::sha1::sha1;
+    use crate::{encoding::hex_encode, sha1::sha1};
 
     use super::*;
     #[test]
@@ -325,10 +642,7 @@ mod test {
         println!("------------------------------------------------------------------------------");
         let dict = if let BencodeType::Dict(dict) = b.node {
             println!("{:?}", String::from_utf8(b.slice.to_vec()));
-            let sss: String = sha1(b.slice)
-                .iter()
-                .map(|b| format!("{:02x}", b).to_string())
-                .collect();
+            let sss = hex_encode(&sha1(b.slice));
             println!("{:?}", sss);
             dict
         } else {
@@ -368,4 +682,321 @@ mod test {
     fn test_torrent_2() {
         let _b = Bencode::from_u8("d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee".as_bytes()).unwrap();
     }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let fixtures = [
+            "i42e",
+            "10:spam1spam1",
+            "li42ei15e5:abcdee",
+            "d3:bar4:spam3:fooi42ee",
+            "lli42ei15e5:abcdeee",
+            "d1:ad1:ai25eee",
+            "d3:abcld1:ad1:al5:heheh5:hehehd5:heheh5:hehehei25ed2:abi10eeeeeee",
+            "lli5eel1:bi1eee",
+            "d3:abcli12ei14ee2:aa3:aaae",
+            "d4:infod5:filesld4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562eee4:name28:Fedora-Budgie-Live-x86_64-38ee",
+            "d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee",
+        ];
+
+        for fixture in fixtures {
+            let b = Bencode::from_u8(fixture.as_bytes()).unwrap();
+            assert_eq!(
+                String::from_utf8(b.node.encode_preserving_order()).unwrap(),
+                fixture
+            );
+            if fixture != "d3:abcli12ei14ee2:aa3:aaae" {
+                assert_eq!(String::from_utf8(b.node.encode()).unwrap(), fixture);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_sorts_keys() {
+        // the keys of this fixture are not sorted, so only the value survives
+        let b = Bencode::from_u8("d3:abcli12ei14ee2:aa3:aaae".as_bytes()).unwrap();
+        let encoded = b.node.encode();
+        assert_eq!(encoded, "d2:aa3:aaa3:abcli12ei14eee".as_bytes());
+        assert_eq!(Bencode::from_u8(&encoded).unwrap().node, b.node);
+        assert_eq!(b.node.encode_preserving_order(), b.slice);
+    }
+
+    #[test]
+    fn test_parse_dict_preserves_order_and_raw_keys() {
+        let input = b"d1:z0:2:\xff\xfei1e1:ali1eee";
+        let b = Bencode::from_u8(input).unwrap();
+        let dict = if let BencodeType::Dict(dict) = &b.node {
+            dict
+        } else {
+            panic!()
+        };
+
+        assert_eq!(
+            dict.keys().collect::<Vec<_>>(),
+            vec![&b"z"[..], &[0xff, 0xfe][..], &b"a"[..]]
+        );
+        assert_eq!(dict.get([0xff, 0xfe]), Some(&BencodeType::Int(1)));
+        assert_eq!(b.node.encode_preserving_order(), input);
+        assert_eq!(b.node.encode(), b"d1:ali1ee1:z0:2:\xff\xfei1ee");
+    }
+
+    #[test]
+    fn test_duplicate_key_in_unsorted_dict() {
+        assert_eq!(
+            Bencode::from_u8(b"d1:bi1e1:ai2e1:bi3ee").unwrap_err(),
+            BencodeError::DuplicateKey { offset: 13 }
+        );
+        assert_eq!(
+            Bencode::from_u8(b"d1:bi1e1:ai2e1:ai3ee").unwrap_err(),
+            BencodeError::DuplicateKey { offset: 13 }
+        );
+    }
+
+    #[test]
+    fn test_encode_values() {
+        let mut dict = BencodeDict::new();
+        dict.insert("zero", BencodeType::Int(0));
+        dict.insert("neg", BencodeType::Int(-17));
+        dict.insert("empty", BencodeType::Str(vec![]));
+        dict.insert("bin", BencodeType::Str(vec![0x00, 0xff]));
+        dict.insert("list", BencodeType::List(vec![]));
+
+        assert_eq!(
+            BencodeType::Dict(dict).encode(),
+            b"d3:bin2:\x00\xff5:empty0:4:listle3:negi-17e4:zeroi0ee"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases: [(&[u8], BencodeError); 17] = [
+            (b"", BencodeError::UnexpectedEof { offset: 0 }),
+            (b"i42", BencodeError::UnexpectedEof { offset: 3 }),
+            (b"5:abc", BencodeError::UnexpectedEof { offset: 5 }),
+            (b"l", BencodeError::UnexpectedEof { offset: 1 }),
+            (
+                b"x",
+                BencodeError::UnexpectedByte {
+                    offset: 0,
+                    byte: b'x',
+                },
+            ),
+            (
+                b"i4x2e",
+                BencodeError::UnexpectedByte {
+                    offset: 2,
+                    byte: b'x',
+                },
+            ),
+            (
+                b"3x:abc",
+                BencodeError::UnexpectedByte {
+                    offset: 1,
+                    byte: b'x',
+                },
+            ),
+            (b"ie", BencodeError::InvalidInteger { offset: 1 }),
+            (b"i-e", BencodeError::InvalidInteger { offset: 1 }),
+            (
+                b"i9223372036854775808e",
+                BencodeError::InvalidInteger { offset: 1 },
+            ),
+            (b"i03e", BencodeError::LeadingZero { offset: 1 }),
+            (b"03:abc", BencodeError::LeadingZero { offset: 0 }),
+            (b"i-0e", BencodeError::NegativeZero { offset: 1 }),
+            (
+                b"99999999999999999999999:a",
+                BencodeError::InvalidLength { offset: 0 },
+            ),
+            (b"di1ei2ee", BencodeError::NonStringKey { offset: 1 }),
+            (b"d1:ai1e1:ai2ee", BencodeError::DuplicateKey { offset: 7 }),
+            (b"i1ei2e", BencodeError::TrailingData { offset: 3 }),
+        ];
+
+        for (input, err) in cases {
+            assert_eq!(Bencode::from_u8(input).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn test_strict_rejects_unsorted_keys() {
+        let strict = DecodeOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let unsorted = "d3:abcli12ei14ee2:aa3:aaae".as_bytes();
+        assert!(Bencode::from_u8(unsorted).is_ok());
+        assert_eq!(
+            Bencode::from_u8_with(unsorted, &strict).unwrap_err(),
+            BencodeError::UnsortedKeys { offset: 16 }
+        );
+        assert!(Bencode::from_u8_with("d2:aa3:aaa3:abcli12ei14eee".as_bytes(), &strict).is_ok());
+    }
+
+    #[test]
+    fn test_nesting_too_deep() {
+        let input = "l".repeat(1_000_000);
+        assert_eq!(
+            Bencode::from_u8(input.as_bytes()).unwrap_err(),
+            BencodeError::NestingTooDeep { offset: 128 }
+        );
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let input = "d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee".as_bytes();
+
+        // every proper prefix of a value is incomplete
+        for len in 0..input.len() {
+            assert!(Bencode::from_u8(&input[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_garbage_input() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let alphabet = b"ilde0123456789:-ax";
+        let fixture =
+            "d3:abcld1:ad1:al5:heheh5:hehehd5:heheh5:hehehei25ed2:abi10eeeeeee".as_bytes();
+        let mut rng = StdRng::seed_from_u64(0xbe5c0de);
+
+        for _ in 0..20_000 {
+            let len = rng.gen_range(0..64);
+            let input: Vec<u8> = (0..len)
+                .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                .collect();
+            // only checks that nothing panics
+            let _ = Bencode::from_u8(&input);
+
+            let mut mutated = fixture.to_vec();
+            let pos = rng.gen_range(0..mutated.len());
+            mutated[pos] = rng.gen();
+            let _ = Bencode::from_u8(&mutated);
+        }
+    }
+
+    #[test]
+    fn test_parse_64_bit_int() {
+        let b = Bencode::from_u8("d6:lengthi4294967296e13:creation datei4102444800ee".as_bytes())
+            .unwrap();
+        let dict = if let BencodeType::Dict(dict) = b.node {
+            dict
+        } else {
+            panic!()
+        };
+        assert_eq!(dict.get("length"), Some(&BencodeType::Int(4294967296)));
+        assert_eq!(
+            dict.get("creation date"),
+            Some(&BencodeType::Int(4102444800))
+        );
+
+        for int in [i64::MIN, i64::MAX] {
+            let input = format!("i{}e", int);
+            let b = Bencode::from_u8(input.as_bytes()).unwrap();
+            assert_eq!(b.node, BencodeType::Int(int));
+            assert_eq!(b.node.encode(), input.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_parse_big_int() {
+        let options = DecodeOptions {
+            big_ints: true,
+            ..Default::default()
+        };
+
+        for input in ["i9223372036854775808e", "i-123456789012345678901234567890e"] {
+            assert!(Bencode::from_u8(input.as_bytes()).is_err());
+
+            let b = Bencode::from_u8_with(input.as_bytes(), &options).unwrap();
+            assert_eq!(
+                b.node,
+                BencodeType::BigInt(String::from(&input[1..input.len() - 1]))
+            );
+            assert_eq!(b.node.encode(), input.as_bytes());
+        }
+
+        // the canonical form is still enforced
+        assert_eq!(
+            Bencode::from_u8_with("i-09223372036854775808e".as_bytes(), &options).unwrap_err(),
+            BencodeError::LeadingZero { offset: 1 }
+        );
+    }
+
+    #[test]
+    fn test_accessors() {
+        let b = Bencode::from_u8(b"d3:bar4:spam3:fooi42e4:listli1ee3:bin1:\xffe")
+            .unwrap()
+            .node;
+
+        assert_eq!(b.get("foo").and_then(BencodeType::as_int), Some(42));
+        assert_eq!(b.get("bar").and_then(BencodeType::as_str), Some("spam"));
+        assert_eq!(
+            b.get("bar").and_then(BencodeType::as_bytes),
+            Some("spam".as_bytes())
+        );
+        assert_eq!(b.get("bin").and_then(BencodeType::as_str), None);
+        assert_eq!(
+            b.get("list").and_then(BencodeType::as_list),
+            Some(&[BencodeType::Int(1)][..])
+        );
+        assert_eq!(b.get("foo").and_then(BencodeType::as_str), None);
+        assert_eq!(b.as_dict().map(BencodeDict::len), Some(4));
+        assert_eq!(b.get("missing"), None);
+        assert_eq!(BencodeType::Int(1).get("foo"), None);
+    }
+
+    #[test]
+    fn test_limits() {
+        let options = DecodeOptions {
+            max_depth: 2,
+            max_string_len: 4,
+            max_elements: 5,
+            max_total_size: 18,
+            ..Default::default()
+        };
+        let cases: [(&[u8], Result<(), BencodeError>); 8] = [
+            (b"lli1eee", Ok(())),
+            (
+                b"llli1eeee",
+                Err(BencodeError::NestingTooDeep { offset: 2 }),
+            ),
+            (b"4:spam", Ok(())),
+            (b"5:spams", Err(BencodeError::StringTooLong { offset: 0 })),
+            (
+                b"99999999999:",
+                Err(BencodeError::StringTooLong { offset: 0 }),
+            ),
+            (b"li1ei2ei3ei4ee", Ok(())),
+            (
+                b"li1ei2ei3ei4ei5ee",
+                Err(BencodeError::TooManyElements { offset: 13 }),
+            ),
+            (
+                b"l4:spam4:spam4:spame",
+                Err(BencodeError::InputTooLarge { offset: 18 }),
+            ),
+        ];
+
+        for (input, res) in cases {
+            assert_eq!(
+                Bencode::from_u8_with(input, &options).map(|_| ()),
+                res,
+                "{:?}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_trusted_options() {
+        let mut input = b"30000000:".to_vec();
+        input.resize(input.len() + 30_000_000, b'a');
+        assert_eq!(
+            Bencode::from_u8(&input).unwrap_err(),
+            BencodeError::StringTooLong { offset: 0 }
+        );
+        assert!(Bencode::from_u8_with(&input, &DecodeOptions::trusted()).is_ok());
+    }
 }
@@ -0,0 +1,133 @@
+use super::{BencodeDict, BencodeError, BencodeType, DecodeOptions, Parser};
+
+/// A parsed bencoded value that borrows from its input instead of copying
+/// strings, alongside the exact bytes it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BencodeRef<'a> {
+    pub node: BencodeRefType<'a>,
+    /// The raw span of the input this value was parsed from.
+    pub slice: &'a [u8],
+}
+
+/// The borrowed counterpart of `BencodeType`. Dictionary entries are kept in
+/// input order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BencodeRefType<'a> {
+    Int(i64),
+    BigInt(&'a str),
+    Str(&'a [u8]),
+    List(Vec<BencodeRef<'a>>),
+    Dict(Vec<(&'a [u8], BencodeRef<'a>)>),
+}
+
+impl<'a> BencodeRef<'a> {
+    pub fn from_u8(bencoded_input: &'a [u8]) -> Result<Self, BencodeError> {
+        BencodeRef::from_u8_with(bencoded_input, &DecodeOptions::default())
+    }
+
+    /// Parses a single bencoded value spanning the whole of `bencoded_input`.
+    pub fn from_u8_with(
+        bencoded_input: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<Self, BencodeError> {
+        if bencoded_input.len() > options.max_total_size {
+            return Err(BencodeError::InputTooLarge {
+                offset: options.max_total_size,
+            });
+        }
+
+        let mut parser = Parser {
+            input: bencoded_input,
+            pos: 0,
+            depth: 0,
+            elements: 0,
+            options,
+        };
+        let value = parser.parse_value()?;
+        if parser.pos != bencoded_input.len() {
+            return Err(BencodeError::TrailingData { offset: parser.pos });
+        }
+        Ok(value)
+    }
+
+    /// Looks up `key` if this value is a dictionary.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&BencodeRef<'a>> {
+        let key = key.as_ref();
+        match &self.node {
+            BencodeRefType::Dict(dict) => dict.iter().find(|(k, _)| *k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Copies the value into an owned `BencodeType` tree.
+    pub fn to_bencode_type(&self) -> BencodeType {
+        match &self.node {
+            BencodeRefType::Int(int) => BencodeType::Int(*int),
+            BencodeRefType::BigInt(digits) => BencodeType::BigInt(String::from(*digits)),
+            BencodeRefType::Str(str) => BencodeType::Str(str.to_vec()),
+            BencodeRefType::List(list) => {
+                BencodeType::List(list.iter().map(BencodeRef::to_bencode_type).collect())
+            }
+            BencodeRefType::Dict(dict) => {
+                let mut result = BencodeDict::new();
+                for (key, val) in dict {
+                    // the parser already rejected duplicate keys
+                    result.push_unchecked(key.to_vec(), val.to_bencode_type());
+                }
+                BencodeType::Dict(result)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencode::Bencode;
+
+    const TORRENT: &str = "d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee";
+
+    #[test]
+    fn test_raw_info_span() {
+        let b = BencodeRef::from_u8(TORRENT.as_bytes()).unwrap();
+        assert_eq!(b.slice, TORRENT.as_bytes());
+
+        let info = b.get("info").unwrap();
+        let start = TORRENT.find("d5:files").unwrap();
+        assert_eq!(info.slice, &TORRENT.as_bytes()[start..TORRENT.len() - 1]);
+
+        let name = info.get("name").unwrap();
+        assert_eq!(
+            name.node,
+            BencodeRefType::Str("Fedora-Budgie-Live-x86_64-38".as_bytes())
+        );
+        assert_eq!(name.slice, "28:Fedora-Budgie-Live-x86_64-38".as_bytes());
+    }
+
+    #[test]
+    fn test_strings_borrow_input() {
+        let input = "l5:abcdei7ee".as_bytes();
+        let b = BencodeRef::from_u8(input).unwrap();
+        let list = if let BencodeRefType::List(list) = &b.node {
+            list
+        } else {
+            panic!()
+        };
+
+        if let BencodeRefType::Str(str) = list[0].node {
+            assert_eq!(str.as_ptr(), input[3..].as_ptr());
+        } else {
+            panic!()
+        }
+        assert_eq!(list[1].slice, "i7e".as_bytes());
+    }
+
+    #[test]
+    fn test_to_bencode_type() {
+        let b = BencodeRef::from_u8(TORRENT.as_bytes()).unwrap();
+        assert_eq!(
+            b.to_bencode_type(),
+            Bencode::from_u8(TORRENT.as_bytes()).unwrap().node
+        );
+    }
+}
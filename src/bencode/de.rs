@@ -0,0 +1,420 @@
+use std::vec;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+
+use super::{Bencode, BencodeError, BencodeType, DecodeOptions};
+
+/// Parses `bytes` and deserializes the bencoded value into `T`. Integers
+/// outside of the `i64` range are accepted, so everything `to_bytes` writes
+/// reads back, e.g. a `u64` above `i64::MAX`.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BencodeError> {
+    let options = DecodeOptions {
+        big_ints: true,
+        ..Default::default()
+    };
+    from_value(Bencode::from_u8_with(bytes, &options)?.node)
+}
+
+/// Deserializes an already parsed `BencodeType` tree into `T`.
+pub fn from_value<T: DeserializeOwned>(value: BencodeType) -> Result<T, BencodeError> {
+    T::deserialize(value)
+}
+
+impl BencodeType {
+    fn invalid_type(&self, exp: &dyn de::Expected) -> BencodeError {
+        let unexpected = match self {
+            BencodeType::Int(int) => de::Unexpected::Signed(*int),
+            BencodeType::BigInt(_) => de::Unexpected::Other("big integer"),
+            BencodeType::Str(str) => de::Unexpected::Bytes(str),
+            BencodeType::List(_) => de::Unexpected::Seq,
+            BencodeType::Dict(_) => de::Unexpected::Map,
+        };
+        de::Error::invalid_type(unexpected, exp)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for BencodeType {
+    type Error = BencodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            BencodeType::Int(int) => visitor.visit_i64(int),
+            BencodeType::BigInt(digits) => {
+                if let Ok(int) = digits.parse::<u64>() {
+                    visitor.visit_u64(int)
+                } else if let Ok(int) = digits.parse::<i128>() {
+                    visitor.visit_i128(int)
+                } else if let Ok(int) = digits.parse::<u128>() {
+                    visitor.visit_u128(int)
+                } else {
+                    visitor.visit_string(digits)
+                }
+            }
+            BencodeType::Str(str) => match String::from_utf8(str) {
+                Ok(str) => visitor.visit_string(str),
+                Err(err) => visitor.visit_byte_buf(err.into_bytes()),
+            },
+            BencodeType::List(list) => visitor.visit_seq(ListAccess {
+                iter: list.into_iter(),
+            }),
+            BencodeType::Dict(dict) => visitor.visit_map(DictAccess {
+                iter: dict.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            BencodeType::Int(0) => visitor.visit_bool(false),
+            BencodeType::Int(1) => visitor.visit_bool(true),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            BencodeType::Str(str) => visitor.visit_byte_buf(str),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // a missing value is expressed by leaving out the dictionary key
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            BencodeType::List(list) => visitor.visit_seq(ListAccess {
+                iter: list.into_iter(),
+            }),
+            // lets plain `Vec<u8>` fields read byte strings
+            BencodeType::Str(str) => visitor.visit_seq(BytesAccess {
+                iter: str.into_iter(),
+            }),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (variant, value) = match self {
+            BencodeType::Str(str) => (str, None),
+            BencodeType::Dict(dict) if dict.len() == 1 => {
+                let (variant, value) = dict.into_iter().next().unwrap();
+                (variant, Some(value))
+            }
+            _ => return Err(self.invalid_type(&visitor)),
+        };
+        let variant = String::from_utf8(variant)
+            .map_err(|_| BencodeError::new("enum variant should be a UTF-8 string"))?;
+
+        visitor.visit_enum(EnumAccess { variant, value })
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct map struct identifier ignored_any
+    }
+}
+
+struct ListAccess {
+    iter: vec::IntoIter<BencodeType>,
+}
+
+impl<'de> de::SeqAccess<'de> for ListAccess {
+    type Error = BencodeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.iter
+            .next()
+            .map(|val| seed.deserialize(val))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct BytesAccess {
+    iter: vec::IntoIter<u8>,
+}
+
+impl<'de> de::SeqAccess<'de> for BytesAccess {
+    type Error = BencodeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.iter
+            .next()
+            .map(|byte| seed.deserialize(byte.into_deserializer()))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct DictAccess {
+    iter: vec::IntoIter<(Vec<u8>, BencodeType)>,
+    value: Option<BencodeType>,
+}
+
+impl<'de> de::MapAccess<'de> for DictAccess {
+    type Error = BencodeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                // keys that are valid UTF-8 reach the visitor as strings
+                seed.deserialize(BencodeType::Str(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(value) => seed.deserialize(value),
+            None => Err(BencodeError::new("next_value called before next_key")),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<BencodeType>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = BencodeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<BencodeType>,
+}
+
+impl VariantAccess {
+    fn value(self) -> Result<BencodeType, BencodeError> {
+        self.value
+            .ok_or_else(|| BencodeError::new("enum variant should carry a value"))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = BencodeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => Err(BencodeError::new("unit variant should not carry a value")),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self.value()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_seq(self.value()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_map(self.value()?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::bencode::to_bytes;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct File {
+        length: u64,
+        path: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Info {
+        files: Vec<File>,
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        #[serde(with = "crate::bencode::bytes")]
+        pieces: Vec<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        private: Option<bool>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Metainfo {
+        announce: String,
+        #[serde(rename = "creation date")]
+        creation_date: Option<u32>,
+        info: Info,
+    }
+
+    #[test]
+    fn test_from_bytes_torrent() {
+        let metainfo: Metainfo = from_bytes("d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144e6:pieces3:\x01\x02\x03ee".as_bytes()).unwrap();
+
+        assert_eq!(
+            metainfo.announce,
+            "http://torrent.fedoraproject.org:6969/announce"
+        );
+        assert_eq!(metainfo.creation_date, Some(1681726664));
+        assert_eq!(metainfo.info.files.len(), 2);
+        assert_eq!(metainfo.info.files[1].length, 2562);
+        assert_eq!(
+            metainfo.info.files[1].path,
+            vec![String::from("Fedora-Spins-38-1.6-x86_64-CHECKSUM")]
+        );
+        assert_eq!(metainfo.info.piece_length, 262144);
+        assert_eq!(metainfo.info.pieces, vec![1, 2, 3]);
+        assert_eq!(metainfo.info.private, None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let metainfo = Metainfo {
+            announce: String::from("udp://tracker.example:80"),
+            creation_date: None,
+            info: Info {
+                files: vec![File {
+                    length: 12,
+                    path: vec![String::from("dir"), String::from("file")],
+                }],
+                name: String::from("name"),
+                piece_length: 16384,
+                pieces: vec![0xff; 20],
+                private: Some(true),
+            },
+        };
+
+        let bytes = to_bytes(&metainfo).unwrap();
+        assert_eq!(from_bytes::<Metainfo>(&bytes).unwrap(), metainfo);
+    }
+
+    #[test]
+    fn test_wide_ints_round_trip() {
+        let bytes = to_bytes(&u64::MAX).unwrap();
+        assert_eq!(from_bytes::<u64>(&bytes).unwrap(), u64::MAX);
+        let bytes = to_bytes(&i128::MIN).unwrap();
+        assert_eq!(from_bytes::<i128>(&bytes).unwrap(), i128::MIN);
+
+        // still range checked against the target type
+        assert!(from_bytes::<i64>(&to_bytes(&u64::MAX).unwrap()).is_err());
+        assert!(from_bytes::<u64>(b"i18446744073709551616e").is_err());
+        assert!(from_bytes::<u64>(b"i-1e").is_err());
+    }
+
+    #[test]
+    fn test_enums() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Message {
+            Ping,
+            Have(u32),
+            Request { index: u32, length: u32 },
+        }
+
+        for message in [
+            Message::Ping,
+            Message::Have(7),
+            Message::Request {
+                index: 1,
+                length: 16384,
+            },
+        ] {
+            let bytes = to_bytes(&message).unwrap();
+            assert_eq!(from_bytes::<Message>(&bytes).unwrap(), message);
+        }
+        assert_eq!(to_bytes(&Message::Have(7)).unwrap(), b"d4:Havei7ee");
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        assert!(from_bytes::<String>(b"i42e").is_err());
+        assert!(from_bytes::<u8>(b"i256e").is_err());
+        assert!(from_bytes::<String>(b"2:\xff\xfe").is_err());
+        assert!(from_bytes::<Metainfo>(b"d8:announce1:ae").is_err());
+        assert_eq!(
+            from_bytes::<Vec<u8>>(b"2:\xff\xfe").unwrap(),
+            vec![0xff, 0xfe]
+        );
+    }
+}
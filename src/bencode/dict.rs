@@ -0,0 +1,277 @@
+use std::{collections::HashMap, fmt, slice, vec};
+
+use super::BencodeType;
+
+/// Dictionaries with more entries than this also keep a hash index.
+const INDEX_THRESHOLD: usize = 16;
+
+/// A bencode dictionary: raw byte string keys mapped to values, kept in the
+/// order they were inserted (for parsed input, the order they appeared in).
+///
+/// Small dictionaries, like most in torrents and tracker messages, are
+/// searched linearly. Larger ones, such as `piece layers` or maps from serde,
+/// also keep an index from key to position so lookups and inserts do not
+/// grow with their size. Equality ignores the order of the entries.
+#[derive(Clone, Default)]
+pub struct BencodeDict {
+    entries: Vec<(Vec<u8>, BencodeType)>,
+    /// Position of every key in `entries`, once there are more than
+    /// `INDEX_THRESHOLD` of them.
+    index: Option<HashMap<Vec<u8>, usize>>,
+}
+
+impl BencodeDict {
+    pub fn new() -> Self {
+        BencodeDict::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &[u8]) -> Option<usize> {
+        match &self.index {
+            Some(index) => index.get(key).copied(),
+            None => self.entries.iter().position(|(k, _)| k == key),
+        }
+    }
+
+    /// Rebuilds the index after entries moved, or drops it if the dictionary
+    /// became small enough to do without.
+    fn reindex(&mut self) {
+        self.index = (self.entries.len() > INDEX_THRESHOLD).then(|| {
+            // reversed so that, like a linear search, the first of duplicate
+            // keys pushed unchecked wins
+            self.entries
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, (k, _))| (k.clone(), i))
+                .collect()
+        });
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&BencodeType> {
+        let index = self.position(key.as_ref())?;
+        Some(&self.entries[index].1)
+    }
+
+    pub fn get_mut(&mut self, key: impl AsRef<[u8]>) -> Option<&mut BencodeType> {
+        let index = self.position(key.as_ref())?;
+        Some(&mut self.entries[index].1)
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `value` under `key`. An existing entry keeps its position and
+    /// has its old value returned; a new entry goes to the end.
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: BencodeType) -> Option<BencodeType> {
+        let key = key.into();
+        match self.get_mut(&key) {
+            Some(old) => Some(std::mem::replace(old, value)),
+            None => {
+                self.push_unchecked(key, value);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Option<BencodeType> {
+        let index = self.position(key.as_ref())?;
+        let (_, value) = self.entries.remove(index);
+        self.reindex();
+        Some(value)
+    }
+
+    /// Iterates over the entries in insertion order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &BencodeType> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Whether the keys are in the strictly ascending byte order that
+    /// canonical bencode requires.
+    pub fn is_sorted(&self) -> bool {
+        self.entries.windows(2).all(|w| w[0].0 < w[1].0)
+    }
+
+    /// Reorders the entries into canonical (byte-wise ascending) key order.
+    pub fn sort_keys(&mut self) {
+        self.entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.reindex();
+    }
+
+    /// Appends an entry without checking for an existing one with the same
+    /// key; callers must guarantee uniqueness.
+    pub(super) fn push_unchecked(&mut self, key: Vec<u8>, value: BencodeType) {
+        match &mut self.index {
+            Some(index) => {
+                index.entry(key.clone()).or_insert(self.entries.len());
+                self.entries.push((key, value));
+            }
+            None => {
+                self.entries.push((key, value));
+                if self.entries.len() > INDEX_THRESHOLD {
+                    self.reindex();
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BencodeDict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BencodeDict")
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl PartialEq for BencodeDict {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+pub struct Iter<'a> {
+    inner: slice::Iter<'a, (Vec<u8>, BencodeType)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], &'a BencodeType);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, v)| (k.as_slice(), v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a BencodeDict {
+    type Item = (&'a [u8], &'a BencodeType);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for BencodeDict {
+    type Item = (Vec<u8>, BencodeType);
+    type IntoIter = vec::IntoIter<(Vec<u8>, BencodeType)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<K: Into<Vec<u8>>> FromIterator<(K, BencodeType)> for BencodeDict {
+    fn from_iter<I: IntoIterator<Item = (K, BencodeType)>>(iter: I) -> Self {
+        let mut dict = BencodeDict::new();
+        for (k, v) in iter {
+            dict.insert(k, v);
+        }
+        dict
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert_keeps_order() {
+        let mut dict = BencodeDict::new();
+        assert_eq!(dict.insert("b", BencodeType::Int(1)), None);
+        assert_eq!(dict.insert(vec![0xff], BencodeType::Int(2)), None);
+        assert_eq!(dict.insert("a", BencodeType::Int(3)), None);
+        assert_eq!(
+            dict.insert("b", BencodeType::Int(4)),
+            Some(BencodeType::Int(1))
+        );
+
+        assert_eq!(
+            dict.keys().collect::<Vec<_>>(),
+            vec![&b"b"[..], &[0xff][..], &b"a"[..]]
+        );
+        assert_eq!(dict.get("b"), Some(&BencodeType::Int(4)));
+        assert!(!dict.is_sorted());
+
+        dict.sort_keys();
+        assert!(dict.is_sorted());
+        assert_eq!(
+            dict.keys().collect::<Vec<_>>(),
+            vec![&b"a"[..], &b"b"[..], &[0xff][..]]
+        );
+
+        assert_eq!(dict.remove("a"), Some(BencodeType::Int(3)));
+        assert_eq!(dict.len(), 2);
+    }
+
+    #[test]
+    fn test_eq_ignores_order() {
+        let a: BencodeDict = [("x", BencodeType::Int(1)), ("y", BencodeType::Int(2))]
+            .into_iter()
+            .collect();
+        let b: BencodeDict = [("y", BencodeType::Int(2)), ("x", BencodeType::Int(1))]
+            .into_iter()
+            .collect();
+        let c: BencodeDict = [("y", BencodeType::Int(2))].into_iter().collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_large_dict() {
+        let key = |i: usize| format!("key{}", i);
+        let mut dict: BencodeDict = (0..1000)
+            .rev()
+            .map(|i| (key(i), BencodeType::Int(i as i64)))
+            .collect();
+        assert_eq!(dict.len(), 1000);
+        assert_eq!(dict.get(key(500)), Some(&BencodeType::Int(500)));
+        assert_eq!(
+            dict.insert(key(500), BencodeType::Int(-1)),
+            Some(BencodeType::Int(500))
+        );
+        assert_eq!(dict.len(), 1000);
+
+        // positions move when sorting and removing
+        dict.sort_keys();
+        assert_eq!(dict.keys().next(), Some(&b"key0"[..]));
+        assert_eq!(dict.get(key(500)), Some(&BencodeType::Int(-1)));
+        assert_eq!(dict.remove(key(0)), Some(BencodeType::Int(0)));
+        assert_eq!(dict.get(key(0)), None);
+        assert_eq!(dict.get(key(999)), Some(&BencodeType::Int(999)));
+
+        // down to where there is no index
+        for i in 1..990 {
+            dict.remove(key(i));
+        }
+        assert_eq!(dict.len(), 10);
+        assert_eq!(dict.get(key(995)), Some(&BencodeType::Int(995)));
+
+        let reversed: BencodeDict = dict.clone().into_iter().rev().collect();
+        assert_eq!(dict, reversed);
+    }
+}
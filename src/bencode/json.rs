@@ -0,0 +1,199 @@
+//! Lossless conversion between bencode and JSON.
+//!
+//! Integers map to numbers, UTF-8 strings to strings, lists to arrays and
+//! dictionaries to objects. What JSON cannot express directly is written as
+//! an object with a single `$`-prefixed tag key:
+//!
+//! - `{"$hex": "ff00"}` for a string that is not valid UTF-8,
+//! - `{"$int": "123456789012345678901234567890"}` for a `BencodeType::BigInt`,
+//! - `{"$dict": [[key, value], ...]}` for a dictionary with a non-UTF-8 key,
+//!   or whose only key starts with `$` and would be mistaken for a tag.
+//!
+//! JSON objects do not keep their key order, so dictionaries come back in
+//! canonical order.
+
+use serde_json::{Map, Value};
+
+use super::{BencodeDict, BencodeError, BencodeType};
+use crate::encoding::{hex_decode, hex_encode};
+
+fn str_to_json(str: &[u8]) -> Value {
+    match std::str::from_utf8(str) {
+        Ok(str) => Value::String(String::from(str)),
+        Err(_) => tagged("$hex", Value::String(hex_encode(str))),
+    }
+}
+
+fn tagged(tag: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(String::from(tag), value);
+    Value::Object(map)
+}
+
+impl BencodeType {
+    pub fn to_json(&self) -> Value {
+        match self {
+            BencodeType::Int(int) => Value::from(*int),
+            BencodeType::BigInt(digits) => tagged("$int", Value::String(digits.clone())),
+            BencodeType::Str(str) => str_to_json(str),
+            BencodeType::List(list) => Value::Array(list.iter().map(|v| v.to_json()).collect()),
+            BencodeType::Dict(dict) => {
+                let plain_keys: Option<Vec<&str>> = dict
+                    .keys()
+                    .map(|key| std::str::from_utf8(key).ok())
+                    .collect();
+
+                match plain_keys {
+                    Some(keys) if !(keys.len() == 1 && keys[0].starts_with('$')) => Value::Object(
+                        keys.into_iter()
+                            .zip(dict.values())
+                            .map(|(key, val)| (String::from(key), val.to_json()))
+                            .collect(),
+                    ),
+                    _ => tagged(
+                        "$dict",
+                        Value::Array(
+                            dict.iter()
+                                .map(|(key, val)| {
+                                    Value::Array(vec![str_to_json(key), val.to_json()])
+                                })
+                                .collect(),
+                        ),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Inverse of `to_json`.
+    pub fn from_json(json: &Value) -> Result<BencodeType, BencodeError> {
+        match json {
+            Value::Number(number) => number
+                .as_i64()
+                .map(BencodeType::Int)
+                .ok_or_else(|| BencodeError::new("JSON number is not an integer")),
+            Value::String(str) => Ok(BencodeType::Str(str.as_bytes().to_vec())),
+            Value::Array(list) => list
+                .iter()
+                .map(BencodeType::from_json)
+                .collect::<Result<_, _>>()
+                .map(BencodeType::List),
+            Value::Object(map) => match map.iter().next() {
+                Some((tag, value)) if map.len() == 1 && tag.starts_with('$') => {
+                    from_tagged(tag, value)
+                }
+                _ => {
+                    let mut dict = BencodeDict::new();
+                    for (key, val) in map {
+                        dict.insert(key.as_str(), BencodeType::from_json(val)?);
+                    }
+                    Ok(BencodeType::Dict(dict))
+                }
+            },
+            Value::Null | Value::Bool(_) => Err(BencodeError::new(
+                "JSON null and booleans have no bencode equivalent",
+            )),
+        }
+    }
+}
+
+fn from_tagged(tag: &str, value: &Value) -> Result<BencodeType, BencodeError> {
+    match (tag, value) {
+        ("$hex", Value::String(hex)) => hex_decode(hex)
+            .map(BencodeType::Str)
+            .ok_or_else(|| BencodeError::new("invalid hex in $hex")),
+        ("$int", Value::String(digits)) => {
+            let magnitude = digits.strip_prefix('-').unwrap_or(digits);
+            if magnitude.is_empty() || !magnitude.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(BencodeError::new("invalid digits in $int"));
+            }
+            Ok(match digits.parse::<i64>() {
+                Ok(int) => BencodeType::Int(int),
+                Err(_) => BencodeType::BigInt(digits.clone()),
+            })
+        }
+        ("$dict", Value::Array(entries)) => {
+            let mut dict = BencodeDict::new();
+            for entry in entries {
+                let (key, val) = match entry.as_array().map(Vec::as_slice) {
+                    Some([key, val]) => (key, val),
+                    _ => return Err(BencodeError::new("$dict entries should be pairs")),
+                };
+                let key = match BencodeType::from_json(key)? {
+                    BencodeType::Str(key) => key,
+                    _ => return Err(BencodeError::new("$dict keys should be strings")),
+                };
+                if dict.insert(key, BencodeType::from_json(val)?).is_some() {
+                    return Err(BencodeError::new("$dict contains a duplicate key"));
+                }
+            }
+            Ok(BencodeType::Dict(dict))
+        }
+        _ => Err(BencodeError::new(&format!("unknown JSON tag {}", tag))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::bencode::{Bencode, DecodeOptions};
+
+    #[test]
+    fn test_to_json() {
+        let b = Bencode::from_u8(b"d4:name3:abc6:pieces2:\xff\x004:sizei7e4:tagsl1:aee")
+            .unwrap()
+            .node;
+        assert_eq!(
+            b.to_json(),
+            json!({
+                "name": "abc",
+                "pieces": {"$hex": "ff00"},
+                "size": 7,
+                "tags": ["a"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let options = DecodeOptions {
+            big_ints: true,
+            ..Default::default()
+        };
+        let fixtures: [&[u8]; 6] = [
+            b"d8:announce9:udp://a:14:infod6:lengthi-5e6:pieces3:\xff\xfe\xfdee",
+            b"d1:\xffi1e1:ai2ee",
+            b"d4:$hex4:ff00e",
+            b"d4:$hex4:ff001:ai1ee",
+            b"li123456789012345678901234567890ei-1e0:dee",
+            b"ld4:$int1:1eld0:leeee",
+        ];
+
+        for fixture in fixtures {
+            let b = Bencode::from_u8_with(fixture, &options).unwrap().node;
+            let json = b.to_json();
+            // survive a trip through JSON text as well
+            let json: Value = serde_json::from_str(&json.to_string()).unwrap();
+            assert_eq!(BencodeType::from_json(&json).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        for json in [
+            json!(null),
+            json!(true),
+            json!(1.5),
+            json!({"$hex": "abc"}),
+            json!({"$int": "12a"}),
+            json!({"$int": "-"}),
+            json!({"$dict": [["a"]]}),
+            json!({"$dict": [[1, 1]]}),
+            json!({"$unknown": 1}),
+        ] {
+            assert!(BencodeType::from_json(&json).is_err(), "{}", json);
+        }
+    }
+}
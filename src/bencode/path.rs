@@ -0,0 +1,184 @@
+use std::fmt;
+
+use super::{BencodeDict, BencodeType};
+
+/// Why a path query such as `info.files.3.length` did not produce a value.
+/// `path` is the prefix of the query up to and including the failing segment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathError {
+    /// A dictionary has no such key, or a list no such index.
+    NotFound { path: String },
+    /// A list was indexed with a segment that is not a number.
+    InvalidIndex { path: String },
+    /// The value exists but has a different type than requested, or a
+    /// segment tried to descend into an integer or string.
+    TypeMismatch {
+        path: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::NotFound { path } => write!(f, "`{}` not found", path),
+            PathError::InvalidIndex { path } => write!(f, "`{}` is not a list index", path),
+            PathError::TypeMismatch { path, expected } => {
+                write!(f, "`{}` is not {}", path, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Splits a path on `.`; a literal dot inside a key is written as `\.` and a
+/// literal backslash as `\\`, e.g. `info.files.0.path\.utf-8`.
+fn segments(path: &str) -> Vec<String> {
+    let mut segments = vec![];
+    let mut segment = String::new();
+    let mut chars = path.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => segment.extend(chars.next()),
+            '.' => segments.push(std::mem::take(&mut segment)),
+            _ => segment.push(ch),
+        }
+    }
+    segments.push(segment);
+    segments
+}
+
+impl BencodeType {
+    /// Follows a dot separated path of dictionary keys and list indices. The
+    /// empty path refers to the value itself.
+    pub fn path(&self, path: &str) -> Result<&BencodeType, PathError> {
+        if path.is_empty() {
+            return Ok(self);
+        }
+
+        let mut node = self;
+        let mut walked = String::new();
+        for segment in segments(path) {
+            if !walked.is_empty() {
+                walked.push('.');
+            }
+            walked.push_str(&segment);
+
+            node = match node {
+                BencodeType::Dict(dict) => dict.get(&segment),
+                BencodeType::List(list) => {
+                    let index = segment
+                        .parse::<usize>()
+                        .map_err(|_| PathError::InvalidIndex {
+                            path: walked.clone(),
+                        })?;
+                    list.get(index)
+                }
+                _ => {
+                    return Err(PathError::TypeMismatch {
+                        path: walked,
+                        expected: "a list or a dictionary",
+                    })
+                }
+            }
+            .ok_or_else(|| PathError::NotFound {
+                path: walked.clone(),
+            })?;
+        }
+        Ok(node)
+    }
+
+    pub fn path_int(&self, path: &str) -> Result<i64, PathError> {
+        typed(self.path(path)?.as_int(), path, "an integer")
+    }
+
+    pub fn path_bytes(&self, path: &str) -> Result<&[u8], PathError> {
+        typed(self.path(path)?.as_bytes(), path, "a string")
+    }
+
+    pub fn path_str(&self, path: &str) -> Result<&str, PathError> {
+        typed(self.path(path)?.as_str(), path, "a UTF-8 string")
+    }
+
+    pub fn path_list(&self, path: &str) -> Result<&[BencodeType], PathError> {
+        typed(self.path(path)?.as_list(), path, "a list")
+    }
+
+    pub fn path_dict(&self, path: &str) -> Result<&BencodeDict, PathError> {
+        typed(self.path(path)?.as_dict(), path, "a dictionary")
+    }
+}
+
+fn typed<T>(value: Option<T>, path: &str, expected: &'static str) -> Result<T, PathError> {
+    value.ok_or_else(|| PathError::TypeMismatch {
+        path: String::from(path),
+        expected,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bencode::Bencode;
+
+    const TORRENT: &str = "d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee";
+
+    #[test]
+    fn test_path() {
+        let b = Bencode::from_u8(TORRENT.as_bytes()).unwrap().node;
+
+        assert_eq!(b.path_int("info.files.1.length"), Ok(2562));
+        assert_eq!(
+            b.path_str("info.files.0.path.0"),
+            Ok("Fedora-Budgie-Live-x86_64-38-1.6.iso")
+        );
+        assert_eq!(b.path_int("info.piece length"), Ok(262144));
+        assert_eq!(b.path_int("creation date"), Ok(1681726664));
+        assert_eq!(b.path_list("info.files").map(|files| files.len()), Ok(2));
+        assert!(b.path_dict("info").is_ok());
+        assert_eq!(b.path(""), Ok(&b));
+    }
+
+    #[test]
+    fn test_path_errors() {
+        let b = Bencode::from_u8(TORRENT.as_bytes()).unwrap().node;
+
+        assert_eq!(
+            b.path_int("info.files.2.length"),
+            Err(PathError::NotFound {
+                path: String::from("info.files.2")
+            })
+        );
+        assert_eq!(
+            b.path("info.files.first"),
+            Err(PathError::InvalidIndex {
+                path: String::from("info.files.first")
+            })
+        );
+        assert_eq!(
+            b.path("announce.0"),
+            Err(PathError::TypeMismatch {
+                path: String::from("announce.0"),
+                expected: "a list or a dictionary"
+            })
+        );
+        assert_eq!(
+            b.path_int("info.name"),
+            Err(PathError::TypeMismatch {
+                path: String::from("info.name"),
+                expected: "an integer"
+            })
+        );
+    }
+
+    #[test]
+    fn test_escaped_dot() {
+        let b = Bencode::from_u8("d4:pathl1:ae10:path.utf-8l1:bee".as_bytes())
+            .unwrap()
+            .node;
+        assert_eq!(b.path_str("path.0"), Ok("a"));
+        assert_eq!(b.path_str("path\\.utf-8.0"), Ok("b"));
+    }
+}
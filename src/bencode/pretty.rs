@@ -0,0 +1,131 @@
+use std::fmt::{self, Write};
+
+use super::BencodeType;
+use crate::encoding::hex_encode;
+
+/// Binary strings longer than this are shown abbreviated.
+const MAX_HEX_BYTES: usize = 32;
+
+/// Writes a string as a quoted, escaped literal when it is valid UTF-8 and as
+/// `<hex ...>` otherwise.
+fn write_str(f: &mut fmt::Formatter<'_>, str: &[u8]) -> fmt::Result {
+    match std::str::from_utf8(str) {
+        Ok(str) => write!(f, "{:?}", str),
+        Err(_) if str.len() > MAX_HEX_BYTES => write!(
+            f,
+            "<hex {}... ({} bytes)>",
+            hex_encode(&str[..MAX_HEX_BYTES]),
+            str.len()
+        ),
+        Err(_) => write!(f, "<hex {}>", hex_encode(str)),
+    }
+}
+
+fn indent(f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+    for _ in 0..level {
+        f.write_str("  ")?;
+    }
+    Ok(())
+}
+
+impl BencodeType {
+    fn write_pretty(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        level: usize,
+        key: Option<&[u8]>,
+    ) -> fmt::Result {
+        match self {
+            BencodeType::Int(int) => write!(f, "{}", int),
+            BencodeType::BigInt(digits) => f.write_str(digits),
+            // concatenated SHA-1 hashes are of no use to a reader
+            BencodeType::Str(str) if key == Some(b"pieces") && str.len() % 20 == 0 => {
+                write!(f, "<{} SHA-1 hashes>", str.len() / 20)
+            }
+            BencodeType::Str(str) => write_str(f, str),
+            BencodeType::List(list) if list.is_empty() => f.write_str("[]"),
+            BencodeType::List(list) => {
+                f.write_str("[\n")?;
+                for item in list {
+                    indent(f, level + 1)?;
+                    item.write_pretty(f, level + 1, None)?;
+                    f.write_str(",\n")?;
+                }
+                indent(f, level)?;
+                f.write_char(']')
+            }
+            BencodeType::Dict(dict) if dict.is_empty() => f.write_str("{}"),
+            BencodeType::Dict(dict) => {
+                f.write_str("{\n")?;
+                for (key, val) in dict {
+                    indent(f, level + 1)?;
+                    write_str(f, key)?;
+                    f.write_str(": ")?;
+                    val.write_pretty(f, level + 1, Some(key))?;
+                    f.write_str(",\n")?;
+                }
+                indent(f, level)?;
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Human readable, indented rendering for debugging: strings are shown as
+/// text when they are valid UTF-8 and as (abbreviated) hex otherwise.
+impl fmt::Display for BencodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_pretty(f, 0, None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bencode::Bencode;
+
+    #[test]
+    fn test_pretty() {
+        let mut input = b"d8:announce9:udp://a:14:infod5:filesld6:lengthi12e4:pathl1:aeee4:name3:abc6:pieces40:".to_vec();
+        input.extend([0xaa; 40]);
+        input.extend(b"e3:key2:\xff\x006:nestedlleee");
+
+        let b = Bencode::from_u8(&input).unwrap();
+        assert_eq!(
+            b.node.to_string(),
+            r#"{
+  "announce": "udp://a:1",
+  "info": {
+    "files": [
+      {
+        "length": 12,
+        "path": [
+          "a",
+        ],
+      },
+    ],
+    "name": "abc",
+    "pieces": <2 SHA-1 hashes>,
+  },
+  "key": <hex ff00>,
+  "nested": [
+    [],
+  ],
+}"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_long_binary() {
+        let mut input = b"40:".to_vec();
+        input.extend([0xff; 40]);
+        let b = Bencode::from_u8(&input).unwrap();
+        assert_eq!(
+            b.node.to_string(),
+            format!("<hex {}... (40 bytes)>", "ff".repeat(32))
+        );
+        assert_eq!(
+            Bencode::from_u8(b"5:a\"b\nc").unwrap().node.to_string(),
+            r#""a\"b\nc""#
+        );
+    }
+}
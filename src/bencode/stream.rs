@@ -0,0 +1,383 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::{BencodeError, BencodeRef, BencodeType, DecodeOptions};
+
+/// Where the scanner is within the value it is looking for the end of.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Scan {
+    /// Expecting the first byte of a value, or the `e` closing a container.
+    #[default]
+    Value,
+    /// Inside `i...e`.
+    Int,
+    /// Reading the length prefix of a string that starts at `start`.
+    StrLen { len: usize, start: usize },
+    /// Skipping over this many remaining bytes of a string.
+    StrBody(usize),
+}
+
+/// A push-style decoder for bencoded values that arrive in pieces, e.g. from
+/// a socket.
+///
+/// Bytes are added with `push`; `decode` returns `Ok(None)` while the next
+/// value is still incomplete. Scanning resumes where the previous call left
+/// off, so each byte is only looked at once before the completed value is
+/// handed to the regular parser. At most `max_total_size` bytes are ever
+/// buffered.
+#[derive(Debug, Default)]
+pub struct StreamDecoder {
+    buf: Vec<u8>,
+    options: DecodeOptions,
+    /// How much of `buf` has been scanned for the end of the next value.
+    scanned: usize,
+    state: Scan,
+    depth: usize,
+    /// Length of the first value in `buf`, once the scanner found its end.
+    complete: Option<usize>,
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        StreamDecoder::default()
+    }
+
+    pub fn with_options(options: DecodeOptions) -> Self {
+        StreamDecoder {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Appends received bytes to the internal buffer and scans them, so
+    /// input that breaks the limits of the options is refused as it arrives
+    /// rather than buffered. Fails with `InputTooLarge` if the buffer would
+    /// grow past `max_total_size`; decode complete values to make room.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), BencodeError> {
+        if self.buf.len().saturating_add(bytes.len()) > self.options.max_total_size {
+            return Err(BencodeError::InputTooLarge {
+                offset: self.options.max_total_size,
+            });
+        }
+        self.buf.extend_from_slice(bytes);
+        self.scan()?;
+        Ok(())
+    }
+
+    /// Bytes that have been pushed but not yet returned as part of a value.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns the next complete value, or `Ok(None)` if more bytes are
+    /// needed. Error offsets are relative to the start of that value; after
+    /// an error the stream cannot be resynchronised and should be dropped.
+    pub fn decode(&mut self) -> Result<Option<BencodeType>, BencodeError> {
+        let end = match self.scan()? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+
+        let value = BencodeRef::from_u8_with(&self.buf[..end], &self.options)?.to_bencode_type();
+        self.buf.drain(..end);
+        self.scanned = 0;
+        self.complete = None;
+        Ok(Some(value))
+    }
+
+    /// Advances the scanner over the buffered bytes and returns the length of
+    /// the first value once it is complete.
+    fn scan(&mut self) -> Result<Option<usize>, BencodeError> {
+        if self.complete.is_some() {
+            return Ok(self.complete);
+        }
+        let mut state = self.state;
+
+        while self.scanned < self.buf.len() {
+            let offset = self.scanned;
+            let byte = self.buf[offset];
+            let mut value_done = false;
+
+            match state {
+                Scan::Value => match byte {
+                    b'i' => state = Scan::Int,
+                    b'l' | b'd' => {
+                        self.depth += 1;
+                        if self.depth > self.options.max_depth {
+                            return Err(BencodeError::NestingTooDeep { offset });
+                        }
+                    }
+                    b'e' if self.depth > 0 => {
+                        self.depth -= 1;
+                        value_done = true;
+                    }
+                    b'0'..=b'9' => {
+                        state = Scan::StrLen {
+                            len: (byte - b'0') as usize,
+                            start: offset,
+                        }
+                    }
+                    _ => return Err(BencodeError::UnexpectedByte { offset, byte }),
+                },
+                Scan::Int => match byte {
+                    b'e' => value_done = true,
+                    b'0'..=b'9' | b'-' => {}
+                    _ => return Err(BencodeError::UnexpectedByte { offset, byte }),
+                },
+                Scan::StrLen { len, start } => match byte {
+                    b':' if len == 0 => value_done = true,
+                    b':' => {
+                        // the string alone would not fit into the buffer
+                        if len > self.options.max_total_size - offset - 1 {
+                            return Err(BencodeError::InputTooLarge {
+                                offset: self.options.max_total_size,
+                            });
+                        }
+                        state = Scan::StrBody(len);
+                    }
+                    b'0'..=b'9' => {
+                        let len = len
+                            .checked_mul(10)
+                            .and_then(|len| len.checked_add((byte - b'0') as usize))
+                            .ok_or(BencodeError::InvalidLength { offset: start })?;
+                        if len > self.options.max_string_len {
+                            return Err(BencodeError::StringTooLong { offset: start });
+                        }
+                        state = Scan::StrLen { len, start };
+                    }
+                    _ => return Err(BencodeError::UnexpectedByte { offset, byte }),
+                },
+                Scan::StrBody(remaining) => {
+                    // skip as much of the string as has arrived in one go
+                    let available = self.buf.len() - offset;
+                    if remaining > available {
+                        self.scanned = self.buf.len();
+                        state = Scan::StrBody(remaining - available);
+                        continue;
+                    }
+                    self.scanned += remaining - 1;
+                    value_done = true;
+                }
+            }
+            self.scanned += 1;
+
+            if value_done {
+                state = Scan::Value;
+                if self.depth == 0 {
+                    self.state = state;
+                    self.complete = Some(self.scanned);
+                    return Ok(self.complete);
+                }
+            }
+        }
+
+        self.state = state;
+        Ok(None)
+    }
+}
+
+/// Reads bencoded values from an `AsyncRead`, such as a TCP stream.
+pub struct BencodeReader<R> {
+    reader: R,
+    decoder: StreamDecoder,
+}
+
+impl<R: AsyncRead + Unpin> BencodeReader<R> {
+    pub fn new(reader: R) -> Self {
+        BencodeReader::with_decoder(reader, StreamDecoder::new())
+    }
+
+    pub fn with_decoder(reader: R, decoder: StreamDecoder) -> Self {
+        BencodeReader { reader, decoder }
+    }
+
+    /// Reads the next value. Returns `Ok(None)` if the reader ends cleanly
+    /// between two values; malformed input is reported as
+    /// `io::ErrorKind::InvalidData`.
+    pub async fn read_value(&mut self) -> io::Result<Option<BencodeType>> {
+        let mut chunk = [0; 4096];
+        loop {
+            if let Some(value) = self.decoder.decode().map_err(invalid_data)? {
+                return Ok(Some(value));
+            }
+
+            let len = self.reader.read(&mut chunk).await?;
+            if len == 0 {
+                if self.decoder.buffered().is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "stream ended in the middle of a bencoded value",
+                ));
+            }
+            self.decoder.push(&chunk[..len]).map_err(invalid_data)?;
+        }
+    }
+
+    /// Returns the reader together with any bytes read past the last value.
+    pub fn into_inner(self) -> (R, Vec<u8>) {
+        (self.reader, self.decoder.buf)
+    }
+}
+
+fn invalid_data(err: BencodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::bencode::Bencode;
+
+    const TORRENT: &str = "d8:announce46:http://torrent.fedoraproject.org:6969/announce13:creation datei1681726664e4:infod5:filesld6:lengthi1967298560e4:pathl36:Fedora-Budgie-Live-x86_64-38-1.6.isoeed6:lengthi2562e4:pathl35:Fedora-Spins-38-1.6-x86_64-CHECKSUMeee4:name28:Fedora-Budgie-Live-x86_64-3812:piece lengthi262144eee";
+
+    #[test]
+    fn test_byte_by_byte() {
+        let expected = Bencode::from_u8(TORRENT.as_bytes()).unwrap().node;
+        let mut decoder = StreamDecoder::new();
+
+        let (last, rest) = TORRENT.as_bytes().split_last().unwrap();
+        for byte in rest {
+            decoder.push(&[*byte]).unwrap();
+            assert_eq!(decoder.decode().unwrap(), None);
+        }
+        decoder.push(&[*last]).unwrap();
+        assert_eq!(decoder.decode().unwrap(), Some(expected));
+        assert!(decoder.buffered().is_empty());
+    }
+
+    #[test]
+    fn test_several_values_in_one_chunk() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push(b"i42e0:4:spamd1:ai1ee3:ab").unwrap();
+
+        assert_eq!(decoder.decode().unwrap(), Some(BencodeType::Int(42)));
+        assert_eq!(decoder.decode().unwrap(), Some(BencodeType::Str(vec![])));
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(BencodeType::Str(b"spam".to_vec()))
+        );
+        assert!(matches!(
+            decoder.decode().unwrap(),
+            Some(BencodeType::Dict(_))
+        ));
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.buffered(), b"3:ab");
+
+        decoder.push(b"c").unwrap();
+        assert_eq!(
+            decoder.decode().unwrap(),
+            Some(BencodeType::Str(b"abc".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_errors_before_value_is_complete() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push(b"li1e").unwrap();
+        assert_eq!(
+            decoder.push(b"x").unwrap_err(),
+            BencodeError::UnexpectedByte {
+                offset: 4,
+                byte: b'x'
+            }
+        );
+
+        let mut decoder = StreamDecoder::new();
+        assert!(decoder.push(b"e").is_err());
+    }
+
+    #[test]
+    fn test_limits_apply_before_value_is_complete() {
+        let options = DecodeOptions {
+            max_depth: 2,
+            max_string_len: 1024,
+            max_total_size: 4096,
+            ..Default::default()
+        };
+
+        let mut decoder = StreamDecoder::with_options(options.clone());
+        assert_eq!(
+            decoder.push(b"lll").unwrap_err(),
+            BencodeError::NestingTooDeep { offset: 2 }
+        );
+
+        let mut decoder = StreamDecoder::with_options(options.clone());
+        assert_eq!(
+            decoder.push(b"l99999").unwrap_err(),
+            BencodeError::StringTooLong { offset: 1 }
+        );
+
+        // within `max_string_len`, but not within `max_total_size` after
+        // what came before it
+        let mut decoder = StreamDecoder::with_options(options.clone());
+        decoder.push(&[b'l'; 2]).unwrap();
+        decoder.push(&[b'i', b'1', b'e'].repeat(1200)).unwrap();
+        assert_eq!(
+            decoder.push(b"1000:").unwrap_err(),
+            BencodeError::InputTooLarge { offset: 4096 }
+        );
+
+        let mut decoder = StreamDecoder::with_options(options.clone());
+        decoder.push(b"l").unwrap();
+        let err = (0..1000)
+            .find_map(|_| decoder.push(b"4:spam").err())
+            .expect("unbounded input was accepted");
+        assert_eq!(err, BencodeError::InputTooLarge { offset: 4096 });
+        assert!(decoder.buffered().len() <= 4096);
+
+        // complete values make room once decoded
+        let mut decoder = StreamDecoder::with_options(options);
+        for _ in 0..1000 {
+            decoder.push(b"4:spam").unwrap();
+            assert!(decoder.decode().unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_errors_from_parser() {
+        let mut decoder = StreamDecoder::new();
+        decoder.push(b"di1ei2ee").unwrap();
+        assert_eq!(
+            decoder.decode().unwrap_err(),
+            BencodeError::NonStringKey { offset: 1 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reader() {
+        let (mut tx, rx) = tokio::io::duplex(7);
+        tokio::spawn(async move {
+            for chunk in TORRENT.as_bytes().chunks(5) {
+                tx.write_all(chunk).await.unwrap();
+            }
+            tx.write_all(b"i1e").await.unwrap();
+        });
+
+        let mut reader = BencodeReader::new(rx);
+        assert_eq!(
+            reader.read_value().await.unwrap(),
+            Some(Bencode::from_u8(TORRENT.as_bytes()).unwrap().node)
+        );
+        assert_eq!(
+            reader.read_value().await.unwrap(),
+            Some(BencodeType::Int(1))
+        );
+        assert_eq!(reader.read_value().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reader_truncated() {
+        let mut reader = BencodeReader::new(&b"li1e"[..]);
+        let err = reader.read_value().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut reader = BencodeReader::new(&b"i1x"[..]);
+        let err = reader.read_value().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,96 @@
+//! Compares SHA-1 throughput of the optimized implementations against the
+//! previous straightforward one. Run with `cargo run --release --bin sha1_bench`.
+
+use std::time::{Duration, Instant};
+
+use torrent::sha1::{self, Sha1};
+
+const INPUT_SIZE: usize = 64 * 1024 * 1024;
+const RUNS: usize = 3;
+
+/// The previous implementation: an 80-word schedule per block and a
+/// branching round loop.
+fn baseline_sha1(input: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = input.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((input.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut ch = [0u32; 80];
+        for (word, bytes) in ch.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for j in 16..80 {
+            ch[j] = (ch[j - 3] ^ ch[j - 8] ^ ch[j - 14] ^ ch[j - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (j, word) in ch.iter().enumerate() {
+            let (f, k) = match j {
+                0..=19 => ((b & c) | ((!b) & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut res = [0; 20];
+    for (chunk, h) in res.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&h.to_be_bytes());
+    }
+    res
+}
+
+fn portable_sha1(input: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::portable();
+    hasher.update(input);
+    hasher.finalize()
+}
+
+/// Best of `RUNS` timings, to dampen noise.
+fn bench(name: &str, input: &[u8], hash: fn(&[u8]) -> [u8; 20]) -> [u8; 20] {
+    let mut best = Duration::MAX;
+    let mut digest = [0; 20];
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        digest = hash(input);
+        best = best.min(start.elapsed());
+    }
+    let mb_per_s = input.len() as f64 / (1024.0 * 1024.0) / best.as_secs_f64();
+    println!("{:<12} {:>9.1} MB/s", name, mb_per_s);
+    digest
+}
+
+fn main() {
+    let input: Vec<u8> = (0..INPUT_SIZE).map(|i| (i * 31 + i / 251) as u8).collect();
+    println!(
+        "hashing {} MiB, hardware support: {}",
+        INPUT_SIZE / (1024 * 1024),
+        sha1::hardware_support()
+    );
+
+    let expected = bench("baseline", &input, baseline_sha1);
+    assert_eq!(bench("portable", &input, portable_sha1), expected);
+    assert_eq!(bench("sha1", &input, sha1::sha1), expected);
+}
@@ -0,0 +1,62 @@
+//! Announces a .torrent file to a UDP tracker (BEP 15) and prints the peers
+//! it returns. The tracker is the second argument, or else the first UDP
+//! tracker of the torrent.
+
+use std::error::Error;
+
+use rand::random;
+use torrent::{
+    torrent_file::TorrentFile,
+    tracker_connection::{AnnounceEvent, AnnounceRequest, UdpTracker},
+};
+
+const PORT: u16 = 34567;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let torrent_file_path = args
+        .next()
+        .expect("usage: udp_announce <file.torrent> [udp://tracker:port]");
+    let torrent = TorrentFile::from_path(torrent_file_path)?;
+
+    let tracker_url = match args.next() {
+        Some(url) => url,
+        None => torrent
+            .announce
+            .iter()
+            .chain(torrent.announce_list.iter().flatten())
+            .find(|url| url.starts_with("udp://"))
+            .cloned()
+            .ok_or("the torrent has no UDP tracker")?,
+    };
+
+    let mut peer_id = *b"-RS0001-000000000000";
+    for byte in &mut peer_id[8..] {
+        *byte = b'0' + random::<u8>() % 10;
+    }
+
+    let mut tracker = UdpTracker::from_url(&tracker_url).await?;
+    let response = tracker
+        .announce(&AnnounceRequest {
+            info_hash: torrent.info_hash,
+            peer_id,
+            downloaded: 0,
+            left: torrent.info.total_length(),
+            uploaded: 0,
+            event: AnnounceEvent::Started,
+            key: random(),
+            num_want: None,
+            port: PORT,
+        })
+        .await?;
+
+    println!(
+        "{}: {} seeders, {} leechers, next announce in {}s",
+        tracker_url, response.seeders, response.leechers, response.interval
+    );
+    for peer in response.peers {
+        println!("{}", peer);
+    }
+    Ok(())
+}
@@ -1,10 +1,17 @@
-use std::{io, net::SocketAddr};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use clap::{Parser, Subcommand};
-use dns_lookup::lookup_host;
-use tokio::net::UdpSocket;
 
-use crate::torrent_file::TorrentId;
+use crate::{
+    create::{create_torrent, CreateOptions},
+    state::AppState,
+    torrent_file::{TorrentFile, TorrentId},
+    tracker_connection::{TrackerError, UdpTracker},
+};
 /// Minimalist torrent client
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -16,7 +23,10 @@ pub struct Args {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Starts to track a torrent
-    Add { torrent_file_path: String },
+    Add {
+        /// Path of a .torrent file, or a magnet link
+        torrent: String,
+    },
     /// Removes a tracked torrent
     Rm { torrent_id: TorrentId },
     /// Lists tracked torrents
@@ -27,52 +37,133 @@ pub enum Commands {
     Continue { torrent_id: TorrentId },
     /// Prints details of a tracked torrent
     Inspect { torrent_id: TorrentId },
+    /// Makes a .torrent file from a file or directory
+    Create(CreateArgs),
 }
 
-pub async fn start_download() -> io::Result<()> {
-    let sock = UdpSocket::bind("0.0.0.0:8080").await?;
+#[derive(clap::Args)]
+pub struct CreateArgs {
+    /// File or directory to share
+    pub path: PathBuf,
+    /// Where to write the .torrent file [default: <name>.torrent]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Tracker URL; comma separated URLs form one tier, repeat for more tiers
+    #[arg(short, long)]
+    pub announce: Vec<String>,
+    /// Name of the torrent [default: the file or directory name]
+    #[arg(short, long)]
+    pub name: Option<String>,
+    /// Piece length in bytes, a power of two [default: based on the size]
+    #[arg(short = 'l', long)]
+    pub piece_length: Option<u64>,
+    /// Free-form comment
+    #[arg(short, long)]
+    pub comment: Option<String>,
+    /// Only use the given trackers to find peers
+    #[arg(short, long)]
+    pub private: bool,
+    /// URL of an HTTP server hosting the files, can be repeated
+    #[arg(short, long)]
+    pub web_seed: Vec<String>,
+    /// Leave out the creation date, so the same files give the same torrent
+    #[arg(long)]
+    pub no_date: bool,
+}
 
-    let ip = lookup_host("open.stealth.si").unwrap();
-    dbg!(&ip);
-    let remote_addr = ip.first().unwrap();
-    sock.connect(SocketAddr::new(remote_addr.to_owned(), 80))
-        .await?;
+pub async fn start_download() -> Result<(), TrackerError> {
+    let mut tracker = UdpTracker::new("open.stealth.si:80").await?;
+    let _connection_id = tracker.connect().await?;
     println!("connected");
-    let mut buf = [0; 1024];
-    let mut payload: Vec<u8> = Vec::new();
-    payload.extend((0x417 as u32).to_be_bytes());
-    payload.extend((0x27101980 as u32).to_be_bytes());
-    payload.extend((0 as u32).to_be_bytes());
-    payload.extend((0xabab as u32).to_be_bytes());
-    // loop {
-    let len = sock.send(&payload).await?;
-    println!("{:?} bytes sent", len);
-
-    let len = sock.recv(&mut buf).await?;
-    println!("{:?} bytes received from {:?}", len, remote_addr);
-
-    let conn_bytes = <[u8; 8]>::try_from(&buf[0..16]).unwrap();
-    let connection_id = u64::from_be_bytes(conn_bytes);
-    // }
     Ok(())
 }
 
-pub fn rm_torrent(torrent_id: &TorrentId) {
-    todo!()
+pub async fn create(args: &CreateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let creation_date = if args.no_date {
+        None
+    } else {
+        Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+    };
+    let options = CreateOptions {
+        name: args.name.clone(),
+        piece_length: args.piece_length,
+        trackers: args
+            .announce
+            .iter()
+            .map(|tier| tier.split(',').map(String::from).collect())
+            .collect(),
+        comment: args.comment.clone(),
+        created_by: Some(format!("torrent.rs {}", env!("CARGO_PKG_VERSION"))),
+        creation_date,
+        private: args.private,
+        web_seeds: args.web_seed.clone(),
+    };
+
+    let bytes = create_torrent(&args.path, &options).await?;
+    let torrent = TorrentFile::from_bytes(&bytes)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => PathBuf::from(format!("{}.torrent", torrent.info.name)),
+    };
+    fs::write(&output, bytes)?;
+
+    println!("{} {}", torrent.info_hash, output.display());
+    Ok(())
+}
+
+/// Error for subcommands that are parsed but do not do anything yet.
+fn not_implemented(command: &str) -> Result<(), Box<dyn std::error::Error>> {
+    Err(format!("`{}` is not implemented yet", command).into())
 }
 
-pub fn ls_torrents() {
-    todo!()
+pub fn rm_torrent(_torrent_id: &TorrentId) -> Result<(), Box<dyn std::error::Error>> {
+    not_implemented("rm")
 }
 
-pub fn pause_torrent(torrent_id: &TorrentId) {
-    todo!()
+pub fn ls_torrents() -> Result<(), Box<dyn std::error::Error>> {
+    not_implemented("ls")
 }
 
-pub fn continue_torrent(torrent_id: &TorrentId) {
-    todo!()
+pub fn pause_torrent(_torrent_id: &TorrentId) -> Result<(), Box<dyn std::error::Error>> {
+    not_implemented("pause")
 }
 
-pub fn inspect_torrent(torrent_id: &TorrentId) {
-    todo!()
+pub fn continue_torrent(_torrent_id: &TorrentId) -> Result<(), Box<dyn std::error::Error>> {
+    not_implemented("continue")
+}
+
+pub fn inspect_torrent(torrent_id: &TorrentId) -> Result<(), Box<dyn std::error::Error>> {
+    let state = AppState::load();
+
+    if let Some(pending) = state.magnets.iter().find(|m| m.id == *torrent_id) {
+        let link = &pending.link;
+        println!("id:        {}", pending.id);
+        if let Some(name) = &link.display_name {
+            println!("name:      {}", name);
+        }
+        println!("info-hash: {}", link.info_hash);
+        println!("metadata:  not fetched yet");
+        println!("magnet:    {}", link);
+        return Ok(());
+    }
+
+    let torrent = state
+        .torrents
+        .iter()
+        .find(|t| t.id == *torrent_id)
+        .ok_or_else(|| format!("no torrent with id {}", torrent_id))?;
+    println!("id:        {}", torrent.id);
+    println!("name:      {}", torrent.info.name);
+    println!("info-hash: {}", torrent.info_hash);
+    println!("size:      {} bytes", torrent.info.total_length());
+    println!(
+        "pieces:    {} of {} bytes",
+        torrent.info.pieces.len(),
+        torrent.info.piece_length
+    );
+    if let Some(comment) = &torrent.comment {
+        println!("comment:   {}", comment);
+    }
+    println!("magnet:    {}", torrent.to_magnet());
+    Ok(())
 }
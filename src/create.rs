@@ -0,0 +1,490 @@
+//! Making .torrent files from a file or a directory on disk.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bencode::{BencodeDict, BencodeType},
+    hasher::{FileRange, HashJob, HashOutcome, HashPool, PieceSource},
+};
+
+/// Smallest piece length chosen automatically, and the smallest accepted.
+pub const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+/// Largest piece length chosen automatically.
+pub const MAX_AUTO_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+/// Automatic piece lengths aim for about this many pieces.
+const TARGET_PIECES: u64 = 1500;
+
+/// What to put into a new torrent besides the files.
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    /// Defaults to the name of the file or directory.
+    pub name: Option<String>,
+    /// Must be a power of two of at least `MIN_PIECE_LENGTH`; chosen from the
+    /// total size when `None`.
+    pub piece_length: Option<u64>,
+    /// Tiers of tracker URLs. The first URL also becomes `announce`, and
+    /// `announce-list` is only written when there is more than one URL.
+    pub trackers: Vec<Vec<String>>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    /// Seconds since the Unix epoch; left out when `None`, which makes the
+    /// output depend on the files alone.
+    pub creation_date: Option<i64>,
+    pub private: bool,
+    pub web_seeds: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum CreateError {
+    Io(io::Error),
+    /// There is no data to share: the path is an empty file or contains no
+    /// files.
+    Empty,
+    InvalidPieceLength(u64),
+    /// File names end up in the torrent as UTF-8.
+    NonUtf8Path(PathBuf),
+}
+
+impl fmt::Display for CreateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreateError::Io(err) => write!(f, "{}", err),
+            CreateError::Empty => write!(f, "nothing to share: no data found"),
+            CreateError::InvalidPieceLength(length) => write!(
+                f,
+                "piece length {} is not a power of two of at least {}",
+                length, MIN_PIECE_LENGTH
+            ),
+            CreateError::NonUtf8Path(path) => {
+                write!(f, "{} is not a UTF-8 path", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CreateError {}
+
+impl From<io::Error> for CreateError {
+    fn from(err: io::Error) -> Self {
+        CreateError::Io(err)
+    }
+}
+
+/// A file to include, with its path inside the torrent.
+struct InputFile {
+    disk_path: PathBuf,
+    components: Vec<String>,
+    length: u64,
+}
+
+fn utf8_name(path: &Path) -> Result<String, CreateError> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or_else(|| CreateError::NonUtf8Path(path.to_path_buf()))
+}
+
+/// Collects the files under `dir` in a stable order: sorted by name, each
+/// directory's contents visited in place. Symbolic links are skipped, so a
+/// link back up the tree cannot recurse forever.
+fn walk(dir: &Path, prefix: &[String], files: &mut Vec<InputFile>) -> Result<(), CreateError> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        let mut components = prefix.to_vec();
+        components.push(utf8_name(&path)?);
+
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            continue;
+        } else if metadata.is_dir() {
+            walk(&path, &components, files)?;
+        } else {
+            files.push(InputFile {
+                disk_path: path,
+                components,
+                length: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// A power of two giving roughly `TARGET_PIECES` pieces.
+pub fn auto_piece_length(total_length: u64) -> u64 {
+    (total_length / TARGET_PIECES)
+        .next_power_of_two()
+        .clamp(MIN_PIECE_LENGTH, MAX_AUTO_PIECE_LENGTH)
+}
+
+/// The file ranges making up each piece, in order, given the path and length
+/// of each file. Pieces run across file boundaries as if the files were one
+/// stream.
+fn piece_sources(
+    files: Vec<(PathBuf, u64)>,
+    piece_length: u64,
+) -> impl Iterator<Item = Vec<FileRange>> {
+    let mut files = files
+        .into_iter()
+        .filter(|(_, length)| *length > 0)
+        .peekable();
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        let mut ranges = vec![];
+        let mut remaining = piece_length;
+        while remaining > 0 {
+            let (path, length) = match files.peek() {
+                Some(file) => file,
+                // the last piece is shorter
+                None => break,
+            };
+            let len = remaining.min(length - offset);
+            ranges.push(FileRange {
+                path: path.clone(),
+                offset,
+                len,
+            });
+            remaining -= len;
+            offset += len;
+            if offset == *length {
+                files.next();
+                offset = 0;
+            }
+        }
+        (!ranges.is_empty()).then_some(ranges)
+    })
+}
+
+async fn hash_pieces(files: &[InputFile], piece_length: u64) -> Result<Vec<u8>, CreateError> {
+    let total_length: u64 = files.iter().map(|file| file.length).sum();
+    let count = total_length.div_ceil(piece_length) as usize;
+    let files = files
+        .iter()
+        .map(|file| (file.disk_path.clone(), file.length))
+        .collect();
+
+    let (pool, mut results) = HashPool::new(16);
+    // jobs are made as the queue takes them, so only the queued pieces'
+    // ranges are held at once
+    let producer = tokio::spawn(async move {
+        for (index, ranges) in piece_sources(files, piece_length).enumerate() {
+            let job = HashJob {
+                index,
+                source: PieceSource::Files(ranges),
+                expected: None,
+            };
+            if pool.submit(job).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut pieces = vec![0; count * 20];
+    let mut hashed = 0;
+    while let Some(result) = results.recv().await {
+        match result.outcome {
+            HashOutcome::Hashed(digest) => {
+                pieces[result.index * 20..][..20].copy_from_slice(&digest);
+                hashed += 1;
+            }
+            HashOutcome::Unreadable(err) => {
+                // no point in reading the rest
+                producer.abort();
+                return Err(CreateError::Io(err));
+            }
+            HashOutcome::Verified | HashOutcome::Mismatch => unreachable!(),
+        }
+    }
+    if hashed < count {
+        return Err(CreateError::Io(io::Error::other(format!(
+            "only {} of {} pieces were hashed",
+            hashed, count
+        ))));
+    }
+    Ok(pieces)
+}
+
+fn str(value: &str) -> BencodeType {
+    BencodeType::Str(value.as_bytes().to_vec())
+}
+
+/// Builds the bencoded .torrent for the file or directory at `path`.
+pub async fn create_torrent(path: &Path, options: &CreateOptions) -> Result<Vec<u8>, CreateError> {
+    let metadata = fs::metadata(path)?;
+    let name = match &options.name {
+        Some(name) => name.clone(),
+        None => utf8_name(&fs::canonicalize(path)?)?,
+    };
+
+    let files = if metadata.is_dir() {
+        let mut files = vec![];
+        walk(path, &[], &mut files)?;
+        files
+    } else {
+        vec![InputFile {
+            disk_path: path.to_path_buf(),
+            components: vec![name.clone()],
+            length: metadata.len(),
+        }]
+    };
+
+    let total_length: u64 = files.iter().map(|file| file.length).sum();
+    if total_length == 0 {
+        return Err(CreateError::Empty);
+    }
+
+    let piece_length = match options.piece_length {
+        Some(length) if length < MIN_PIECE_LENGTH || !length.is_power_of_two() => {
+            return Err(CreateError::InvalidPieceLength(length));
+        }
+        Some(length) => length,
+        None => auto_piece_length(total_length),
+    };
+
+    let mut info = BencodeDict::new();
+    info.insert("name", str(&name));
+    info.insert("piece length", BencodeType::Int(piece_length as i64));
+    info.insert(
+        "pieces",
+        BencodeType::Str(hash_pieces(&files, piece_length).await?),
+    );
+    if options.private {
+        info.insert("private", BencodeType::Int(1));
+    }
+    if metadata.is_dir() {
+        let files = files
+            .iter()
+            .map(|file| {
+                let mut entry = BencodeDict::new();
+                entry.insert("length", BencodeType::Int(file.length as i64));
+                entry.insert(
+                    "path",
+                    BencodeType::List(file.components.iter().map(|c| str(c)).collect()),
+                );
+                BencodeType::Dict(entry)
+            })
+            .collect();
+        info.insert("files", BencodeType::List(files));
+    } else {
+        info.insert("length", BencodeType::Int(total_length as i64));
+    }
+
+    let mut root = BencodeDict::new();
+    if let Some(announce) = options.trackers.iter().flatten().next() {
+        root.insert("announce", str(announce));
+    }
+    if options.trackers.iter().flatten().count() > 1 {
+        let tiers = options
+            .trackers
+            .iter()
+            .filter(|tier| !tier.is_empty())
+            .map(|tier| BencodeType::List(tier.iter().map(|url| str(url)).collect()))
+            .collect();
+        root.insert("announce-list", BencodeType::List(tiers));
+    }
+    if let Some(comment) = &options.comment {
+        root.insert("comment", str(comment));
+    }
+    if let Some(created_by) = &options.created_by {
+        root.insert("created by", str(created_by));
+    }
+    if let Some(creation_date) = options.creation_date {
+        root.insert("creation date", BencodeType::Int(creation_date));
+    }
+    if !options.web_seeds.is_empty() {
+        root.insert(
+            "url-list",
+            BencodeType::List(options.web_seeds.iter().map(|url| str(url)).collect()),
+        );
+    }
+    root.insert("info", BencodeType::Dict(info));
+
+    Ok(BencodeType::Dict(root).encode())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sha1::sha1, torrent_file::TorrentFile};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("torrent-create-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn data(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_mul(seed)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_create_directory() {
+        let dir = temp_dir("dir");
+        let a = data(40_000, 3);
+        let b = data(0, 5);
+        let c = data(10_000, 7);
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("c"), &c).unwrap();
+        fs::write(dir.join("a"), &a).unwrap();
+        fs::write(dir.join("b"), &b).unwrap();
+
+        let options = CreateOptions {
+            name: Some(String::from("root")),
+            piece_length: Some(MIN_PIECE_LENGTH),
+            trackers: vec![
+                vec![String::from("udp://one.test:80")],
+                vec![String::from("udp://two.test:80")],
+            ],
+            comment: Some(String::from("build 42")),
+            created_by: Some(String::from("test")),
+            creation_date: Some(1700000000),
+            private: true,
+            web_seeds: vec![String::from("http://seed.test/")],
+        };
+        let bytes = create_torrent(&dir, &options).await.unwrap();
+        let torrent = TorrentFile::from_bytes(&bytes).unwrap();
+
+        assert_eq!(torrent.announce.as_deref(), Some("udp://one.test:80"));
+        assert_eq!(torrent.announce_list, options.trackers);
+        assert_eq!(torrent.comment, options.comment);
+        assert_eq!(torrent.created_by, options.created_by);
+        assert_eq!(torrent.creation_date, Some(1700000000));
+        assert_eq!(torrent.web_seeds, options.web_seeds);
+        assert!(torrent.info.private);
+        assert_eq!(torrent.info.name, "root");
+
+        let paths: Vec<_> = torrent
+            .info
+            .files
+            .iter()
+            .map(|file| (file.path.join("/"), file.length))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (String::from("a"), 40_000),
+                (String::from("b"), 0),
+                (String::from("sub/c"), 10_000)
+            ]
+        );
+
+        // the pieces of the files as one stream
+        let mut stream = a;
+        stream.extend(c);
+        let expected: Vec<[u8; 20]> = stream.chunks(MIN_PIECE_LENGTH as usize).map(sha1).collect();
+        assert_eq!(torrent.info.pieces, expected);
+
+        // canonical, so creating again gives the same bytes
+        assert_eq!(create_torrent(&dir, &options).await.unwrap(), bytes);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_single_file() {
+        let dir = temp_dir("single");
+        let path = dir.join("file.bin");
+        let contents = data(100_000, 11);
+        fs::write(&path, &contents).unwrap();
+
+        let bytes = create_torrent(&path, &CreateOptions::default())
+            .await
+            .unwrap();
+        let torrent = TorrentFile::from_bytes(&bytes).unwrap();
+        assert_eq!(torrent.announce, None);
+        assert!(torrent.announce_list.is_empty());
+        assert_eq!(torrent.creation_date, None);
+        assert_eq!(torrent.info.name, "file.bin");
+        assert_eq!(torrent.info.length, Some(100_000));
+        assert_eq!(torrent.info.piece_length, MIN_PIECE_LENGTH);
+        assert_eq!(torrent.info.pieces.len(), 7);
+        assert_eq!(torrent.info.pieces[6], sha1(&contents[6 * 16384..]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_errors() {
+        let dir = temp_dir("errors");
+        assert!(matches!(
+            create_torrent(&dir, &CreateOptions::default()).await,
+            Err(CreateError::Empty)
+        ));
+
+        fs::write(dir.join("a"), b"abc").unwrap();
+        let options = CreateOptions {
+            piece_length: Some(20000),
+            ..Default::default()
+        };
+        assert!(matches!(
+            create_torrent(&dir, &options).await,
+            Err(CreateError::InvalidPieceLength(20000))
+        ));
+        assert!(matches!(
+            create_torrent(&dir.join("missing"), &options).await,
+            Err(CreateError::Io(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_create_skips_symlinks() {
+        let dir = temp_dir("symlinks");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("a"), data(1000, 3)).unwrap();
+        // a loop back to the top, and a link to a file
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("sub").join("a"), dir.join("b")).unwrap();
+
+        let bytes = create_torrent(&dir, &CreateOptions::default())
+            .await
+            .unwrap();
+        let torrent = TorrentFile::from_bytes(&bytes).unwrap();
+        let paths: Vec<_> = torrent
+            .info
+            .files
+            .iter()
+            .map(|file| file.path.join("/"))
+            .collect();
+        assert_eq!(paths, ["sub/a"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hash_pieces_unreadable() {
+        let dir = temp_dir("unreadable");
+        fs::write(dir.join("a"), data(100_000, 3)).unwrap();
+        let file = |name: &str, length| InputFile {
+            disk_path: dir.join(name),
+            components: vec![String::from(name)],
+            length,
+        };
+        // gone since the directory was walked
+        let files = [file("a", 100_000), file("missing", 1_000_000)];
+        assert!(matches!(
+            hash_pieces(&files, MIN_PIECE_LENGTH).await,
+            Err(CreateError::Io(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_auto_piece_length() {
+        assert_eq!(auto_piece_length(1), MIN_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(1500 * 1024 * 1024), 1024 * 1024);
+        assert_eq!(auto_piece_length(u64::MAX / 2), MAX_AUTO_PIECE_LENGTH);
+    }
+}
@@ -0,0 +1,58 @@
+//! Hex and percent encoding of raw bytes, for hashes and URIs.
+
+/// Lowercase hex, two digits per byte.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hex digits of either case. `None` for an odd length or anything
+/// that is not a hex digit.
+pub fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = char::from(pair[0]).to_digit(16)?;
+            let low = char::from(pair[1]).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        })
+        .collect()
+}
+
+/// Escapes everything but unreserved characters (RFC 3986), so values
+/// cannot be mistaken for the `&` and `=` of a query.
+pub fn percent_encode(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex_encode(&[0x00, 0xab, 0x7f]), "00ab7f");
+        assert_eq!(hex_decode("00aB7F"), Some(vec![0x00, 0xab, 0x7f]));
+        assert_eq!(hex_decode(""), Some(vec![]));
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+        // accepted by `u8::from_str_radix`, but not a hex digit
+        assert_eq!(hex_decode("+f"), None);
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode(b"a-b.c_d~9"), "a-b.c_d~9");
+        assert_eq!(percent_encode(b"a b&c=\xff"), "a%20b%26c%3D%FF");
+    }
+}
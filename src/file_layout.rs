@@ -0,0 +1,364 @@
+//! Where the bytes of a torrent live: the files of a torrent are treated as
+//! one stream cut into pieces, so a piece can cover the end of one file and
+//! the start of the next.
+
+use std::ops::Range;
+
+use crate::torrent_file::{TorrentFile, TorrentFileInfo};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutFile {
+    /// Path inside the torrent, starting with the torrent name for multi-file
+    /// torrents.
+    pub path: Vec<String>,
+    /// Position of the first byte in the stream of all files.
+    pub offset: u64,
+    pub length: u64,
+    /// A BEP 47 padding file: zeros that are never stored on disk.
+    pub padding: bool,
+}
+
+impl LayoutFile {
+    fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+/// A run of bytes inside one file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    /// Index into `FileLayout::files`.
+    pub file: usize,
+    /// Position inside the file.
+    pub offset: u64,
+    pub len: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileLayout {
+    files: Vec<LayoutFile>,
+    piece_length: u64,
+    total_length: u64,
+}
+
+impl FileLayout {
+    pub fn new(info: &TorrentFileInfo) -> Self {
+        let mut files = vec![];
+        let mut offset = 0;
+
+        match (info.length, &info.file_tree) {
+            (Some(length), _) => files.push(LayoutFile {
+                path: vec![info.name.clone()],
+                offset: 0,
+                length,
+                padding: false,
+            }),
+            // v2 pieces never span files, which is the same as every file
+            // being followed by padding up to the next piece
+            (None, Some(tree)) if !info.has_v1() => {
+                let single = tree.len() == 1 && tree[0].path.len() == 1;
+                for (i, file) in tree.iter().enumerate() {
+                    let mut path = if single {
+                        vec![]
+                    } else {
+                        vec![info.name.clone()]
+                    };
+                    path.extend(file.path.iter().cloned());
+                    files.push(LayoutFile {
+                        path,
+                        offset,
+                        length: file.length,
+                        padding: false,
+                    });
+                    offset += file.length;
+
+                    let gap = offset.next_multiple_of(info.piece_length) - offset;
+                    if gap > 0 && i + 1 < tree.len() {
+                        files.push(LayoutFile {
+                            path: vec![String::from(".pad"), gap.to_string()],
+                            offset,
+                            length: gap,
+                            padding: true,
+                        });
+                        offset += gap;
+                    }
+                }
+            }
+            (None, _) => {
+                for file in &info.files {
+                    let mut path = vec![info.name.clone()];
+                    path.extend(file.path.iter().cloned());
+                    files.push(LayoutFile {
+                        path,
+                        offset,
+                        length: file.length,
+                        padding: file.is_padding(),
+                    });
+                    offset += file.length;
+                }
+            }
+        }
+
+        FileLayout {
+            total_length: files.last().map_or(0, LayoutFile::end),
+            files,
+            piece_length: info.piece_length,
+        }
+    }
+
+    pub fn from_torrent(torrent: &TorrentFile) -> Self {
+        FileLayout::new(&torrent.info)
+    }
+
+    pub fn files(&self) -> &[LayoutFile] {
+        &self.files
+    }
+
+    pub fn total_length(&self) -> u64 {
+        self.total_length
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.total_length.div_ceil(self.piece_length) as usize
+    }
+
+    /// Range of piece `index` in the stream of all files; the last piece is
+    /// usually shorter than the others.
+    pub fn piece_range(&self, index: usize) -> Range<u64> {
+        assert!(index < self.piece_count(), "piece {} out of range", index);
+        let start = index as u64 * self.piece_length;
+        start..(start + self.piece_length).min(self.total_length)
+    }
+
+    pub fn piece_len(&self, index: usize) -> u64 {
+        let range = self.piece_range(index);
+        range.end - range.start
+    }
+
+    /// The file segments covering `range` of the stream, in order. Empty
+    /// files never appear.
+    pub fn segments(&self, range: Range<u64>) -> Vec<Segment> {
+        let mut segments = vec![];
+        // first file ending after the start of the range
+        let first = self.files.partition_point(|file| file.end() <= range.start);
+
+        for (index, file) in self.files.iter().enumerate().skip(first) {
+            if file.offset >= range.end {
+                break;
+            }
+            if file.length == 0 {
+                continue;
+            }
+            let start = range.start.max(file.offset);
+            let end = range.end.min(file.end());
+            segments.push(Segment {
+                file: index,
+                offset: start - file.offset,
+                len: end - start,
+            });
+        }
+        segments
+    }
+
+    pub fn piece_segments(&self, index: usize) -> Vec<Segment> {
+        self.segments(self.piece_range(index))
+    }
+
+    /// Pieces holding any byte of file `index`. For an empty file this is
+    /// the empty range at the piece where it would start.
+    pub fn file_pieces(&self, index: usize) -> Range<usize> {
+        let file = &self.files[index];
+        let first = (file.offset / self.piece_length) as usize;
+        if file.length == 0 {
+            return first..first;
+        }
+        first..file.end().div_ceil(self.piece_length) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::torrent_file::{TorrentFileInfoFile, TorrentFileInfoV2File};
+
+    fn info(piece_length: u64, lengths: &[(u64, &str)]) -> TorrentFileInfo {
+        let files: Vec<TorrentFileInfoFile> = lengths
+            .iter()
+            .enumerate()
+            .map(|(i, &(length, attr))| TorrentFileInfoFile {
+                length,
+                path: vec![format!("file{}", i)],
+                attr: String::from(attr),
+            })
+            .collect();
+        let total: u64 = files.iter().map(|file| file.length).sum();
+        TorrentFileInfo {
+            name: String::from("root"),
+            piece_length,
+            pieces: vec![[0; 20]; total.div_ceil(piece_length) as usize],
+            private: false,
+            length: None,
+            files,
+            file_tree: None,
+        }
+    }
+
+    fn segment(file: usize, offset: u64, len: u64) -> Segment {
+        Segment { file, offset, len }
+    }
+
+    #[test]
+    fn test_layout() {
+        // piece length 4: |aaab|bbbb|bcc|, with an empty file between b and c
+        let layout = FileLayout::new(&info(4, &[(3, ""), (6, ""), (0, ""), (2, "")]));
+
+        assert_eq!(layout.piece_count(), 3);
+        assert_eq!(layout.piece_len(2), 3);
+        assert_eq!(layout.files()[1].path, ["root", "file1"]);
+        assert_eq!(
+            layout.piece_segments(0),
+            vec![segment(0, 0, 3), segment(1, 0, 1)]
+        );
+        assert_eq!(layout.piece_segments(1), vec![segment(1, 1, 4)]);
+        assert_eq!(
+            layout.piece_segments(2),
+            vec![segment(1, 5, 1), segment(3, 0, 2)]
+        );
+
+        assert_eq!(layout.file_pieces(0), 0..1);
+        assert_eq!(layout.file_pieces(1), 0..3);
+        assert_eq!(layout.file_pieces(2), 2..2);
+        assert_eq!(layout.file_pieces(3), 2..3);
+    }
+
+    #[test]
+    fn test_single_file() {
+        let mut single = info(4, &[]);
+        single.name = String::from("a.txt");
+        single.length = Some(10);
+        let layout = FileLayout::new(&single);
+
+        assert_eq!(layout.files()[0].path, ["a.txt"]);
+        assert_eq!(layout.piece_count(), 3);
+        assert_eq!(layout.piece_segments(2), vec![segment(0, 8, 2)]);
+        assert_eq!(layout.file_pieces(0), 0..3);
+    }
+
+    #[test]
+    fn test_padding() {
+        // the padding file aligns file2 to the start of piece 1
+        let layout = FileLayout::new(&info(4, &[(3, ""), (1, "p"), (4, "")]));
+
+        assert!(layout.files()[1].padding);
+        assert!(!layout.files()[2].padding);
+        assert_eq!(
+            layout.piece_segments(0),
+            vec![segment(0, 0, 3), segment(1, 0, 1)]
+        );
+        assert_eq!(layout.piece_segments(1), vec![segment(2, 0, 4)]);
+        assert_eq!(layout.file_pieces(2), 1..2);
+    }
+
+    #[test]
+    fn test_v2_alignment() {
+        let mut v2 = info(4, &[]);
+        v2.file_tree = Some(
+            [(3, "a"), (0, "b"), (4, "c"), (1, "d")]
+                .iter()
+                .map(|&(length, name)| TorrentFileInfoV2File {
+                    path: vec![String::from(name)],
+                    length,
+                    pieces_root: None,
+                    piece_layer: vec![],
+                })
+                .collect(),
+        );
+        let layout = FileLayout::new(&v2);
+
+        // |aaa_|cccc|d|: every file starts its own piece
+        let files: Vec<(&str, u64, bool)> = layout
+            .files()
+            .iter()
+            .map(|f| (f.path.last().unwrap().as_str(), f.offset, f.padding))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("a", 0, false),
+                ("1", 3, true),
+                ("b", 4, false),
+                ("c", 4, false),
+                ("d", 8, false)
+            ]
+        );
+        assert_eq!(layout.files()[0].path, ["root", "a"]);
+        assert_eq!(layout.total_length(), 9);
+        assert_eq!(layout.piece_count(), 3);
+        assert_eq!(layout.piece_segments(1), vec![segment(3, 0, 4)]);
+        assert_eq!(layout.file_pieces(4), 2..3);
+    }
+
+    #[test]
+    fn test_random_layouts() {
+        let mut rng = StdRng::seed_from_u64(0xf11e5);
+
+        for _ in 0..500 {
+            let piece_length = 1 << rng.gen_range(0..6);
+            let lengths: Vec<(u64, &str)> = (0..rng.gen_range(1..10))
+                .map(|_| {
+                    let length = if rng.gen_bool(0.2) {
+                        0
+                    } else {
+                        rng.gen_range(1..100)
+                    };
+                    (length, if rng.gen_bool(0.1) { "p" } else { "" })
+                })
+                .collect();
+            let info = info(piece_length, &lengths);
+            let layout = FileLayout::new(&info);
+            if layout.total_length() == 0 {
+                continue;
+            }
+
+            // the owner of every byte of the stream
+            let owners: Vec<(usize, u64)> = lengths
+                .iter()
+                .enumerate()
+                .flat_map(|(file, &(length, _))| (0..length).map(move |offset| (file, offset)))
+                .collect();
+
+            let mut covered = vec![];
+            for piece in 0..layout.piece_count() {
+                let segments = layout.piece_segments(piece);
+                let bytes: Vec<(usize, u64)> = segments
+                    .iter()
+                    .flat_map(|s| (s.offset..s.offset + s.len).map(move |offset| (s.file, offset)))
+                    .collect();
+                assert_eq!(bytes.len() as u64, layout.piece_len(piece));
+                assert!(segments.iter().all(|s| s.len > 0));
+
+                for &(file, _) in &bytes {
+                    assert!(layout.file_pieces(file).contains(&piece));
+                }
+                covered.extend(bytes);
+            }
+            // pieces cover the stream exactly once, in order
+            assert_eq!(covered, owners);
+
+            // and files only claim pieces they have bytes in
+            for file in 0..lengths.len() {
+                for piece in layout.file_pieces(file) {
+                    assert!(layout.piece_segments(piece).iter().any(|s| s.file == file));
+                }
+            }
+
+            // arbitrary ranges agree with the pieces
+            let start = rng.gen_range(0..layout.total_length());
+            let end = rng.gen_range(start..=layout.total_length());
+            let len: u64 = layout.segments(start..end).iter().map(|s| s.len).sum();
+            assert_eq!(len, end - start);
+        }
+    }
+}
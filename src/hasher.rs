@@ -0,0 +1,275 @@
+//! Piece hashing on a pool of blocking threads, for rechecking downloads and
+//! creating torrents without hashing one piece at a time.
+//!
+//! Jobs go in through a bounded queue and results come back on a channel, so
+//! at most `queue_len` jobs are buffered and `workers` are being hashed at any
+//! time however fast they are submitted.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::sha1::Sha1;
+
+/// Part of a file on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileRange {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Where the data of a piece comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PieceSource {
+    Bytes(Vec<u8>),
+    /// The piece is the concatenation of these ranges; pieces of multi-file
+    /// torrents can span several files.
+    Files(Vec<FileRange>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashJob {
+    pub index: usize,
+    pub source: PieceSource,
+    /// The hash to verify against, or `None` to just compute it.
+    pub expected: Option<[u8; 20]>,
+}
+
+#[derive(Debug)]
+pub enum HashOutcome {
+    Verified,
+    Mismatch,
+    /// The digest of a job without an expected hash.
+    Hashed([u8; 20]),
+    /// The data could not be read, e.g. a file that is missing or too short.
+    Unreadable(io::Error),
+}
+
+#[derive(Debug)]
+pub struct HashResult {
+    pub index: usize,
+    pub outcome: HashOutcome,
+}
+
+impl HashResult {
+    /// Whether the piece is known to be good.
+    pub fn is_verified(&self) -> bool {
+        matches!(self.outcome, HashOutcome::Verified)
+    }
+}
+
+impl HashJob {
+    fn digest(&self) -> io::Result<[u8; 20]> {
+        let mut hasher = Sha1::new();
+        match &self.source {
+            PieceSource::Bytes(bytes) => hasher.update(bytes),
+            PieceSource::Files(ranges) => {
+                let mut buf = vec![0; 64 * 1024];
+                for range in ranges {
+                    let mut file = File::open(&range.path)?;
+                    file.seek(SeekFrom::Start(range.offset))?;
+                    let mut file = file.take(range.len);
+                    let mut read = 0;
+                    loop {
+                        let n = file.read(&mut buf)?;
+                        if n == 0 {
+                            break;
+                        }
+                        hasher.update(&buf[..n]);
+                        read += n as u64;
+                    }
+                    if read < range.len {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            format!("{} is too short", range.path.display()),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(hasher.finalize())
+    }
+
+    fn run(self) -> HashResult {
+        let outcome = match (self.digest(), self.expected) {
+            (Err(err), _) => HashOutcome::Unreadable(err),
+            (Ok(digest), None) => HashOutcome::Hashed(digest),
+            (Ok(digest), Some(expected)) if digest == expected => HashOutcome::Verified,
+            (Ok(_), Some(_)) => HashOutcome::Mismatch,
+        };
+        HashResult {
+            index: self.index,
+            outcome,
+        }
+    }
+}
+
+/// Handle for submitting jobs to the pool. Dropping it (and any clones)
+/// shuts the pool down once the queued jobs are done, after which the result
+/// channel closes. Dropping the result receiver shuts it down too: jobs not
+/// yet started are skipped and `submit` fails from then on.
+#[derive(Clone)]
+pub struct HashPool {
+    jobs: mpsc::Sender<HashJob>,
+}
+
+impl HashPool {
+    /// Starts a pool with one worker per core. Must be called from within a
+    /// tokio runtime.
+    pub fn new(queue_len: usize) -> (HashPool, mpsc::Receiver<HashResult>) {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        HashPool::with_workers(workers, queue_len)
+    }
+
+    pub fn with_workers(
+        workers: usize,
+        queue_len: usize,
+    ) -> (HashPool, mpsc::Receiver<HashResult>) {
+        let (jobs_tx, mut jobs_rx) = mpsc::channel::<HashJob>(queue_len);
+        let (results_tx, results_rx) = mpsc::channel(queue_len);
+        let permits = Arc::new(Semaphore::new(workers.max(1)));
+
+        tokio::spawn(async move {
+            while let Some(job) = jobs_rx.recv().await {
+                // the semaphore is never closed
+                let permit = Arc::clone(&permits).acquire_owned().await.unwrap();
+                // nobody wants the results any more; returning drops the
+                // queue, which fails `submit`
+                if results_tx.is_closed() {
+                    return;
+                }
+                let results = results_tx.clone();
+                tokio::task::spawn_blocking(move || {
+                    if results.is_closed() {
+                        return;
+                    }
+                    // keep the worker busy until the result is taken, so a
+                    // slow consumer holds back the queue
+                    let _ = results.blocking_send(job.run());
+                    drop(permit);
+                });
+            }
+        });
+
+        (HashPool { jobs: jobs_tx }, results_rx)
+    }
+
+    /// Queues a job, waiting while the queue is full. Gives the job back if
+    /// the pool has shut down.
+    pub async fn submit(&self, job: HashJob) -> Result<(), HashJob> {
+        self.jobs.send(job).await.map_err(|err| err.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sha1::sha1;
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("torrent-hasher-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    async fn collect(mut results: mpsc::Receiver<HashResult>) -> Vec<HashResult> {
+        let mut all = vec![];
+        while let Some(result) = results.recv().await {
+            all.push(result);
+        }
+        all.sort_by_key(|result| result.index);
+        all
+    }
+
+    #[tokio::test]
+    async fn test_verify_bytes() {
+        let (pool, results) = HashPool::with_workers(2, 2);
+        // far more jobs than fit in the queues, so submitting has to wait for
+        // results to be taken
+        tokio::spawn(async move {
+            for index in 0..20 {
+                let data = vec![index as u8; 1000];
+                let expected = if index % 3 == 0 { [0; 20] } else { sha1(&data) };
+                pool.submit(HashJob {
+                    index,
+                    source: PieceSource::Bytes(data),
+                    expected: Some(expected),
+                })
+                .await
+                .unwrap();
+            }
+        });
+
+        let results = collect(results).await;
+        assert_eq!(results.len(), 20);
+        for (index, result) in results.iter().enumerate() {
+            assert_eq!(result.index, index);
+            assert_eq!(result.is_verified(), index % 3 != 0, "piece {}", index);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stops_without_receiver() {
+        let (pool, results) = HashPool::with_workers(1, 1);
+        drop(results);
+
+        let job = |index| HashJob {
+            index,
+            source: PieceSource::Bytes(vec![0; 1000]),
+            expected: None,
+        };
+        let mut submitted = 0;
+        while pool.submit(job(submitted)).await.is_ok() {
+            submitted += 1;
+            assert!(submitted < 100, "the pool kept taking jobs");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_ranges() {
+        let a = temp_file("a", b"0123456789");
+        let b = temp_file("b", b"abcdef");
+
+        let (pool, results) = HashPool::new(4);
+        let range = |path: &PathBuf, offset, len| FileRange {
+            path: path.clone(),
+            offset,
+            len,
+        };
+        let jobs = [
+            // spans the end of one file and the start of the next
+            vec![range(&a, 6, 4), range(&b, 0, 3)],
+            vec![range(&b, 3, 3)],
+            // past the end of the file
+            vec![range(&b, 3, 10)],
+            vec![range(&a.with_extension("missing"), 0, 1)],
+        ];
+        for (index, ranges) in jobs.into_iter().enumerate() {
+            pool.submit(HashJob {
+                index,
+                source: PieceSource::Files(ranges),
+                expected: None,
+            })
+            .await
+            .unwrap();
+        }
+        drop(pool);
+
+        let results = collect(results).await;
+        assert!(matches!(results[0].outcome, HashOutcome::Hashed(d) if d == sha1(b"6789abc")));
+        assert!(matches!(results[1].outcome, HashOutcome::Hashed(d) if d == sha1(b"def")));
+        assert!(matches!(results[2].outcome, HashOutcome::Unreadable(_)));
+        assert!(matches!(results[3].outcome, HashOutcome::Unreadable(_)));
+
+        std::fs::remove_file(a).unwrap();
+        std::fs::remove_file(b).unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+pub mod bencode;
+pub mod commands;
+pub mod create;
+pub mod encoding;
+pub mod file_layout;
+pub mod hasher;
+pub mod magnet;
+pub mod metadata;
+pub mod sanitize;
+pub mod sha1;
+pub mod sha256;
+pub mod state;
+pub mod torrent_file;
+pub mod tracker_connection;
@@ -0,0 +1,509 @@
+//! Magnet links (BEP 9): a torrent identified by its info-hash alone, with
+//! optional hints on where to find peers and metadata. v2 torrents are
+//! identified by their SHA-256 info-hash as a multihash (BEP 52).
+
+use std::{fmt, ops::RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    encoding::{hex_decode, percent_encode},
+    state::AppState,
+    torrent_file::{InfoHash, InfoHashV2, TorrentFile, TorrentId},
+};
+
+/// Multihash prefix of a SHA-256 digest: function 0x12, 0x20 bytes long.
+const SHA256_MULTIHASH: &str = "1220";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MagnetError {
+    /// The URI does not start with `magnet:?`.
+    NotAMagnet,
+    /// There is no `xt=urn:btih:` parameter.
+    MissingInfoHash,
+    InvalidInfoHash(String),
+    /// A parameter whose value cannot be understood.
+    InvalidParameter {
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for MagnetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MagnetError::NotAMagnet => write!(f, "not a magnet link"),
+            MagnetError::MissingInfoHash => write!(f, "magnet link has no BitTorrent info-hash"),
+            MagnetError::InvalidInfoHash(hash) => write!(f, "invalid info-hash {}", hash),
+            MagnetError::InvalidParameter { key, value } => {
+                write!(f, "invalid magnet parameter {}={}", key, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MagnetError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MagnetLink {
+    /// `xt=urn:btih:`, or the truncated `info_hash_v2` for v2-only links.
+    pub info_hash: InfoHash,
+    /// `xt=urn:btmh:`, the info-hash of a v2 or hybrid torrent.
+    #[serde(default)]
+    pub info_hash_v2: Option<InfoHashV2>,
+    /// `dn`, a name to show until the metadata is known.
+    pub display_name: Option<String>,
+    /// `xl`, the total size in bytes.
+    pub exact_length: Option<u64>,
+    /// `tr`, tracker URLs.
+    pub trackers: Vec<String>,
+    /// `ws`, web seed URLs.
+    pub web_seeds: Vec<String>,
+    /// `x.pe`, addresses of peers to try first, as `host:port`.
+    pub peers: Vec<String>,
+    /// `so`, indices of the files to download; empty means all of them.
+    pub select_only: Vec<RangeInclusive<usize>>,
+}
+
+/// A magnet link waiting for its metadata, under the id it was added with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingMagnet {
+    pub id: TorrentId,
+    pub link: MagnetLink,
+}
+
+/// Decodes RFC 4648 base32 without padding, as used for 32 character
+/// info-hashes. Case is ignored.
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut buffer = 0u64;
+    let mut bits = 0;
+
+    for ch in input.bytes() {
+        let value = match ch.to_ascii_uppercase() {
+            ch @ b'A'..=b'Z' => ch - b'A',
+            ch @ b'2'..=b'7' => ch - b'2' + 26,
+            _ => return None,
+        };
+        buffer = buffer << 5 | value as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Decodes `%XX` escapes, and `+` as a space as is common in query strings.
+fn percent_decode(input: &str) -> Option<String> {
+    let mut out = vec![];
+    let mut bytes = input.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                out.extend(hex_decode(std::str::from_utf8(&hex).ok()?)?);
+            }
+            b'+' => out.push(b' '),
+            byte => out.push(byte),
+        }
+    }
+    Some(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Parses the `so` list: indices and ranges such as `0,2,4-6`.
+fn parse_select_only(value: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    value
+        .split(',')
+        .map(|item| match item.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start <= end).then_some(start..=end)
+            }
+            None => item.parse().ok().map(|index| index..=index),
+        })
+        .collect()
+}
+
+impl InfoHash {
+    /// Parses the 40 character hex or 32 character base32 form.
+    pub fn from_magnet_hash(hash: &str) -> Option<Self> {
+        let bytes = match hash.len() {
+            40 => hex_decode(hash)?,
+            32 => base32_decode(hash)?,
+            _ => return None,
+        };
+        Some(InfoHash(bytes.try_into().ok()?))
+    }
+}
+
+impl InfoHashV2 {
+    /// Parses the hex multihash form: `1220` followed by the SHA-256.
+    pub fn from_magnet_hash(hash: &str) -> Option<Self> {
+        let hex = hash.strip_prefix(SHA256_MULTIHASH)?;
+        Some(InfoHashV2(hex_decode(hex)?.try_into().ok()?))
+    }
+}
+
+impl MagnetLink {
+    pub fn parse(uri: &str) -> Result<Self, MagnetError> {
+        let query = uri
+            .strip_prefix("magnet:?")
+            .ok_or(MagnetError::NotAMagnet)?;
+
+        let mut info_hash = None;
+        let mut link = MagnetLink {
+            info_hash: InfoHash([0; 20]),
+            info_hash_v2: None,
+            display_name: None,
+            exact_length: None,
+            trackers: vec![],
+            web_seeds: vec![],
+            peers: vec![],
+            select_only: vec![],
+        };
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, raw_value) = param.split_once('=').unwrap_or((param, ""));
+            let invalid = || MagnetError::InvalidParameter {
+                key: String::from(key),
+                value: String::from(raw_value),
+            };
+            let value = percent_decode(raw_value).ok_or_else(invalid)?;
+
+            // keys may carry a suffix to number repeated parameters: tr.1=
+            let base_key = key.split_once('.').map_or(key, |(base, suffix)| {
+                if suffix.bytes().all(|b| b.is_ascii_digit()) {
+                    base
+                } else {
+                    key
+                }
+            });
+            match base_key {
+                "xt" => {
+                    let invalid_hash =
+                        |hash: &str| MagnetError::InvalidInfoHash(String::from(hash));
+                    // other hash types are skipped
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        info_hash = Some(
+                            InfoHash::from_magnet_hash(hash).ok_or_else(|| invalid_hash(hash))?,
+                        );
+                    } else if let Some(hash) = value.strip_prefix("urn:btmh:") {
+                        link.info_hash_v2 = Some(
+                            InfoHashV2::from_magnet_hash(hash).ok_or_else(|| invalid_hash(hash))?,
+                        );
+                    }
+                }
+                "dn" => link.display_name = Some(value),
+                "xl" => link.exact_length = Some(value.parse().map_err(|_| invalid())?),
+                "tr" => link.trackers.push(value),
+                "ws" => link.web_seeds.push(value),
+                "x.pe" => link.peers.push(value),
+                "so" => link.select_only = parse_select_only(&value).ok_or_else(invalid)?,
+                // unknown parameters are allowed by the format
+                _ => {}
+            }
+        }
+
+        link.info_hash = info_hash
+            .or(link.info_hash_v2.map(|hash| hash.truncated()))
+            .ok_or(MagnetError::MissingInfoHash)?;
+        Ok(link)
+    }
+
+    /// Whether file `index` should be downloaded according to `so`.
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.select_only.is_empty() || self.select_only.iter().any(|r| r.contains(&index))
+    }
+}
+
+/// Renders the link as a `magnet:?` URI, with the info-hashes in hex: `btih`
+/// for v1, `btmh` for v2, and both for hybrid torrents.
+impl fmt::Display for MagnetLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("magnet:?")?;
+        match self.info_hash_v2 {
+            Some(v2) if v2.truncated() == self.info_hash => {
+                write!(f, "xt=urn:btmh:{}{}", SHA256_MULTIHASH, v2.to_hex())?;
+            }
+            Some(v2) => write!(
+                f,
+                "xt=urn:btih:{}&xt=urn:btmh:{}{}",
+                self.info_hash.to_hex(),
+                SHA256_MULTIHASH,
+                v2.to_hex()
+            )?,
+            None => write!(f, "xt=urn:btih:{}", self.info_hash.to_hex())?,
+        }
+        if let Some(name) = &self.display_name {
+            write!(f, "&dn={}", percent_encode(name.as_bytes()))?;
+        }
+        if let Some(length) = self.exact_length {
+            write!(f, "&xl={}", length)?;
+        }
+        for tracker in &self.trackers {
+            write!(f, "&tr={}", percent_encode(tracker.as_bytes()))?;
+        }
+        for web_seed in &self.web_seeds {
+            write!(f, "&ws={}", percent_encode(web_seed.as_bytes()))?;
+        }
+        for peer in &self.peers {
+            write!(f, "&x.pe={}", percent_encode(peer.as_bytes()))?;
+        }
+        if !self.select_only.is_empty() {
+            let ranges: Vec<String> = self
+                .select_only
+                .iter()
+                .map(|range| match range.start() == range.end() {
+                    true => range.start().to_string(),
+                    false => format!("{}-{}", range.start(), range.end()),
+                })
+                .collect();
+            write!(f, "&so={}", ranges.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl TorrentFile {
+    /// A magnet link to share this torrent: its name, size, web seeds and
+    /// every tracker, `announce` first and without duplicates.
+    pub fn to_magnet(&self) -> MagnetLink {
+        let mut trackers: Vec<String> = vec![];
+        let tiers = self.announce_list.iter().flatten();
+        for url in self.announce.iter().chain(tiers) {
+            if !trackers.contains(url) {
+                trackers.push(url.clone());
+            }
+        }
+
+        MagnetLink {
+            info_hash: self.info_hash,
+            info_hash_v2: self.info_hash_v2,
+            display_name: Some(self.info.name.clone()),
+            exact_length: Some(self.info.total_length()),
+            trackers,
+            web_seeds: self.web_seeds.clone(),
+            peers: vec![],
+            select_only: vec![],
+        }
+    }
+}
+
+/// Starts tracking a magnet link; the torrent is completed once its metadata
+/// has been fetched from peers. Returns the new id.
+pub fn add_magnet(uri: &str) -> Result<TorrentId, Box<dyn std::error::Error>> {
+    let link = MagnetLink::parse(uri)?;
+    let mut state = AppState::load();
+    let id = state.add_magnet(link);
+    state.save()?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        bencode::{BencodeDict, BencodeType},
+        sha256::{pieces_root, BLOCK_SIZE},
+    };
+
+    const HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    #[test]
+    fn test_parse() {
+        let uri = format!(
+            "magnet:?xt=urn:btih:{}&dn=Some+File%20Name.iso&xl=1234\
+             &tr=udp%3A%2F%2Ftracker.test%3A80&tr.1=http://other.test/announce\
+             &ws=http%3A%2F%2Fseed.test%2F&x.pe=10.0.0.1:6881&x.pe=[::1]:6881\
+             &so=0,2,4-6&unknown=1",
+            HASH
+        );
+        let link = MagnetLink::parse(&uri).unwrap();
+
+        assert_eq!(link.info_hash.to_hex(), HASH);
+        assert_eq!(link.display_name.as_deref(), Some("Some File Name.iso"));
+        assert_eq!(link.exact_length, Some(1234));
+        assert_eq!(
+            link.trackers,
+            ["udp://tracker.test:80", "http://other.test/announce"]
+        );
+        assert_eq!(link.web_seeds, ["http://seed.test/"]);
+        assert_eq!(link.peers, ["10.0.0.1:6881", "[::1]:6881"]);
+        assert_eq!(link.select_only, vec![0..=0, 2..=2, 4..=6]);
+        assert!(link.is_selected(5));
+        assert!(!link.is_selected(3));
+    }
+
+    #[test]
+    fn test_base32() {
+        // the same hash as HASH, base32 encoded, in either case
+        for hash in [
+            "YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK",
+            "yex6dqdlxisuvhoj6um3gnnkpqjwpkek",
+        ] {
+            let link = MagnetLink::parse(&format!("magnet:?xt=urn:btih:{}", hash)).unwrap();
+            assert_eq!(link.info_hash.to_hex(), HASH);
+            assert!(link.trackers.is_empty());
+            assert!(link.is_selected(0));
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let link = MagnetLink {
+            info_hash: InfoHash::from_magnet_hash(HASH).unwrap(),
+            info_hash_v2: None,
+            display_name: Some(String::from("a & b=c 100%.iso")),
+            exact_length: Some(42),
+            trackers: vec![
+                String::from("udp://tracker.test:80/announce?key=1&x=2"),
+                String::from("http://other.test/"),
+            ],
+            web_seeds: vec![String::from("http://seed.test/a+b")],
+            peers: vec![String::from("[::1]:6881")],
+            select_only: vec![0..=0, 2..=5],
+        };
+        let uri = link.to_string();
+        assert_eq!(
+            uri,
+            format!(
+                "magnet:?xt=urn:btih:{}&dn=a%20%26%20b%3Dc%20100%25.iso&xl=42\
+                 &tr=udp%3A%2F%2Ftracker.test%3A80%2Fannounce%3Fkey%3D1%26x%3D2\
+                 &tr=http%3A%2F%2Fother.test%2F&ws=http%3A%2F%2Fseed.test%2Fa%2Bb\
+                 &x.pe=%5B%3A%3A1%5D%3A6881&so=0,2-5",
+                HASH
+            )
+        );
+        assert_eq!(MagnetLink::parse(&uri), Ok(link));
+    }
+
+    #[test]
+    fn test_from_torrent() {
+        let mut input = b"d8:announce15:udp://a.test:8013:announce-listll15:udp://a.test:8015:udp://b.test:80el15:udp://c.test:80ee4:infod6:lengthi5e4:name7:a b.txt12:piece lengthi4e6:pieces40:".to_vec();
+        input.extend([0xaa; 40]);
+        input.extend(b"e8:url-list17:http://seed.test/e");
+        let torrent = TorrentFile::from_bytes(&input).unwrap();
+
+        let link = torrent.to_magnet();
+        assert_eq!(link.info_hash, torrent.info_hash);
+        assert_eq!(link.display_name.as_deref(), Some("a b.txt"));
+        assert_eq!(link.exact_length, Some(5));
+        assert_eq!(
+            link.trackers,
+            ["udp://a.test:80", "udp://b.test:80", "udp://c.test:80"]
+        );
+        assert_eq!(link.web_seeds, ["http://seed.test/"]);
+        assert_eq!(MagnetLink::parse(&link.to_string()), Ok(link));
+    }
+
+    const HASH_V2: &str = "d8dd32ac93357c368556af3ac1d95c9d76bd0dff6fa9833ecdac3d53134efabb";
+
+    #[test]
+    fn test_parse_v2() {
+        let link = MagnetLink::parse(&format!("magnet:?xt=urn:btmh:1220{}", HASH_V2)).unwrap();
+        let v2 = link.info_hash_v2.unwrap();
+        assert_eq!(v2.to_hex(), HASH_V2);
+        assert_eq!(link.info_hash, v2.truncated());
+
+        // hybrid, in either order
+        for uri in [
+            format!("magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}", HASH, HASH_V2),
+            format!("magnet:?xt=urn:btmh:1220{}&xt=urn:btih:{}", HASH_V2, HASH),
+        ] {
+            let link = MagnetLink::parse(&uri).unwrap();
+            assert_eq!(link.info_hash.to_hex(), HASH);
+            assert_eq!(link.info_hash_v2, Some(v2));
+            assert_eq!(
+                link.to_string(),
+                format!("magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}", HASH, HASH_V2)
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_v2_torrent() {
+        let data = b"hello";
+        let mut file = BencodeDict::new();
+        file.insert("length", BencodeType::Int(data.len() as i64));
+        file.insert(
+            "pieces root",
+            BencodeType::Str(pieces_root(data).unwrap().to_vec()),
+        );
+        let mut node = BencodeDict::new();
+        node.insert("", BencodeType::Dict(file));
+        let mut tree = BencodeDict::new();
+        tree.insert("a.txt", BencodeType::Dict(node));
+        let mut info = BencodeDict::new();
+        info.insert("file tree", BencodeType::Dict(tree));
+        info.insert("meta version", BencodeType::Int(2));
+        info.insert("name", BencodeType::Str(b"a.txt".to_vec()));
+        info.insert("piece length", BencodeType::Int(BLOCK_SIZE as i64));
+        let mut root = BencodeDict::new();
+        root.insert("info", BencodeType::Dict(info));
+        let torrent = TorrentFile::from_bytes(&BencodeType::Dict(root).encode()).unwrap();
+
+        let link = torrent.to_magnet();
+        let v2 = torrent.info_hash_v2.unwrap();
+        assert_eq!(link.info_hash_v2, Some(v2));
+        // the truncated hash is not a v1 info-hash, so there is no `btih`
+        assert_eq!(
+            link.to_string(),
+            format!("magnet:?xt=urn:btmh:1220{}&dn=a.txt&xl=5", v2.to_hex())
+        );
+        assert_eq!(MagnetLink::parse(&link.to_string()), Ok(link));
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("http://example.com", MagnetError::NotAMagnet),
+            ("magnet:?dn=x", MagnetError::MissingInfoHash),
+            ("magnet:?xt=urn:sha1:aaaa", MagnetError::MissingInfoHash),
+            (
+                "magnet:?xt=urn:btmh:1220aaaa",
+                MagnetError::InvalidInfoHash(String::from("1220aaaa")),
+            ),
+            (
+                // a SHA-1 multihash
+                "magnet:?xt=urn:btmh:1114c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+                MagnetError::InvalidInfoHash(String::from(
+                    "1114c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+                )),
+            ),
+            (
+                "magnet:?xt=urn:btih:abc",
+                MagnetError::InvalidInfoHash(String::from("abc")),
+            ),
+            (
+                "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1",
+                MagnetError::InvalidInfoHash(String::from("YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1")),
+            ),
+            (
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&xl=big",
+                MagnetError::InvalidParameter {
+                    key: String::from("xl"),
+                    value: String::from("big"),
+                },
+            ),
+            (
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&so=3-1",
+                MagnetError::InvalidParameter {
+                    key: String::from("so"),
+                    value: String::from("3-1"),
+                },
+            ),
+            (
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=%zz",
+                MagnetError::InvalidParameter {
+                    key: String::from("dn"),
+                    value: String::from("%zz"),
+                },
+            ),
+        ];
+        for (uri, err) in cases {
+            assert_eq!(MagnetLink::parse(uri), Err(err), "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Collects the `info` dictionary of a magnet link from peers (BEP 9), who
+//! send it in 16 KiB pieces after announcing its size in the extension
+//! handshake.
+
+use std::fmt;
+
+use crate::torrent_file::InfoHash;
+
+/// Size of every metadata piece but the last.
+pub const METADATA_PIECE_SIZE: usize = 16 * 1024;
+
+/// Largest metadata accepted from a peer. The size comes from the peer, so it
+/// is bounded before anything is allocated.
+pub const MAX_METADATA_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataError {
+    /// The size announced by a peer is zero or above `MAX_METADATA_SIZE`.
+    InvalidSize(usize),
+    /// Some pieces have not been received yet.
+    Incomplete,
+    /// The assembled bytes do not hash to the info-hash; all pieces were
+    /// discarded.
+    HashMismatch,
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::InvalidSize(size) => write!(f, "invalid metadata size {}", size),
+            MetadataError::Incomplete => write!(f, "metadata is incomplete"),
+            MetadataError::HashMismatch => write!(f, "metadata does not match the info-hash"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+#[derive(Debug)]
+pub struct MetadataAssembler {
+    info_hash: InfoHash,
+    buffer: Vec<u8>,
+    received: Vec<bool>,
+}
+
+impl MetadataAssembler {
+    pub fn new(info_hash: InfoHash, size: usize) -> Result<Self, MetadataError> {
+        if size == 0 || size > MAX_METADATA_SIZE {
+            return Err(MetadataError::InvalidSize(size));
+        }
+        Ok(MetadataAssembler {
+            info_hash,
+            buffer: vec![0; size],
+            received: vec![false; size.div_ceil(METADATA_PIECE_SIZE)],
+        })
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Pieces still to request, in order.
+    pub fn missing(&self) -> impl Iterator<Item = usize> + '_ {
+        self.received
+            .iter()
+            .enumerate()
+            .filter(|(_, &received)| !received)
+            .map(|(index, _)| index)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received.iter().all(|&received| received)
+    }
+
+    /// Stores piece `index`. Returns false, keeping nothing, if the index is
+    /// out of range or the piece has the wrong length.
+    pub fn add_piece(&mut self, index: usize, data: &[u8]) -> bool {
+        if index >= self.received.len() {
+            return false;
+        }
+        let start = index * METADATA_PIECE_SIZE;
+        let end = (start + METADATA_PIECE_SIZE).min(self.buffer.len());
+        if data.len() != end - start {
+            return false;
+        }
+        self.buffer[start..end].copy_from_slice(data);
+        self.received[index] = true;
+        true
+    }
+
+    /// Checks the complete metadata against the info-hash and returns it.
+    /// Either way the assembler starts over with every piece missing; after
+    /// a mismatch because there is no telling which peer sent a bad one.
+    pub fn finish(&mut self) -> Result<Vec<u8>, MetadataError> {
+        if !self.is_complete() {
+            return Err(MetadataError::Incomplete);
+        }
+        self.received.fill(false);
+        if !self.info_hash.matches(&self.buffer) {
+            return Err(MetadataError::HashMismatch);
+        }
+        let size = self.buffer.len();
+        Ok(std::mem::replace(&mut self.buffer, vec![0; size]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::torrent_file::InfoHashV2;
+
+    fn metadata(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let data = metadata(2 * METADATA_PIECE_SIZE + 100);
+        let mut assembler = MetadataAssembler::new(InfoHash::of(&data), data.len()).unwrap();
+        assert_eq!(assembler.piece_count(), 3);
+
+        let pieces: Vec<&[u8]> = data.chunks(METADATA_PIECE_SIZE).collect();
+        assert!(assembler.add_piece(2, pieces[2]));
+        assert!(assembler.add_piece(0, pieces[0]));
+        assert_eq!(assembler.missing().collect::<Vec<_>>(), [1]);
+        assert!(!assembler.is_complete());
+
+        // wrong lengths and indices are refused
+        assert!(!assembler.add_piece(1, pieces[2]));
+        assert!(!assembler.add_piece(3, pieces[2]));
+
+        assert!(assembler.add_piece(1, pieces[1]));
+        assert_eq!(assembler.finish(), Ok(data.clone()));
+
+        // the assembler starts over rather than handing out the data twice
+        assert_eq!(assembler.finish(), Err(MetadataError::Incomplete));
+        assert_eq!(assembler.missing().count(), 3);
+        for (index, piece) in pieces.iter().enumerate() {
+            assembler.add_piece(index, piece);
+        }
+        assert_eq!(assembler.finish(), Ok(data));
+    }
+
+    #[test]
+    fn test_incomplete() {
+        let data = metadata(METADATA_PIECE_SIZE + 1);
+        let mut assembler = MetadataAssembler::new(InfoHash::of(&data), data.len()).unwrap();
+        assert_eq!(assembler.finish(), Err(MetadataError::Incomplete));
+
+        assembler.add_piece(1, &data[METADATA_PIECE_SIZE..]);
+        assert_eq!(assembler.finish(), Err(MetadataError::Incomplete));
+        assert_eq!(assembler.missing().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn test_truncated_v2_hash() {
+        let data = metadata(100);
+        let hash = InfoHashV2::of(&data).truncated();
+        let mut assembler = MetadataAssembler::new(hash, data.len()).unwrap();
+        assembler.add_piece(0, &data);
+        assert_eq!(assembler.finish(), Ok(data));
+    }
+
+    #[test]
+    fn test_hash_mismatch() {
+        let data = metadata(METADATA_PIECE_SIZE + 1);
+        let mut assembler = MetadataAssembler::new(InfoHash::of(&data), data.len()).unwrap();
+
+        let mut bad = data[..METADATA_PIECE_SIZE].to_vec();
+        bad[0] ^= 1;
+        assembler.add_piece(0, &bad);
+        assembler.add_piece(1, &data[METADATA_PIECE_SIZE..]);
+        assert_eq!(assembler.finish(), Err(MetadataError::HashMismatch));
+        assert_eq!(assembler.missing().collect::<Vec<_>>(), [0, 1]);
+
+        assembler.add_piece(0, &data[..METADATA_PIECE_SIZE]);
+        assembler.add_piece(1, &data[METADATA_PIECE_SIZE..]);
+        assert_eq!(assembler.finish(), Ok(data));
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let hash = InfoHash([0; 20]);
+        assert_eq!(
+            MetadataAssembler::new(hash, 0).unwrap_err(),
+            MetadataError::InvalidSize(0)
+        );
+        assert_eq!(
+            MetadataAssembler::new(hash, MAX_METADATA_SIZE + 1).unwrap_err(),
+            MetadataError::InvalidSize(MAX_METADATA_SIZE + 1)
+        );
+    }
+}
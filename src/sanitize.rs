@@ -0,0 +1,297 @@
+//! Makes file paths from torrents safe to create under a download directory.
+//!
+//! Paths in a torrent come from whoever made it. Components that could
+//! escape the download directory or that some file system cannot store are
+//! rewritten rather than rejected, so such torrents still download, and names
+//! that end up the same are made unique.
+
+use std::collections::{HashMap, HashSet};
+
+/// Device names Windows reserves in every directory, with or without an
+/// extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest component most file systems accept, in bytes.
+const MAX_COMPONENT_LEN: usize = 255;
+
+/// The longest prefix of `s` that fits in `max` bytes.
+fn truncate(s: &str, max: usize) -> &str {
+    let mut end = max.min(s.len());
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Rewrites one path component, or returns `None` if it should be dropped
+/// because it only refers to the current or parent directory (or nothing).
+pub fn sanitize_component(component: &str) -> Option<String> {
+    // separators would split the component, and the rest is invalid on
+    // Windows; `:` also takes care of drive letters and alternate streams
+    let mut clean: String = component
+        .chars()
+        .map(|ch| match ch {
+            '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        })
+        .collect();
+
+    // Windows ignores trailing dots and spaces, which turns `..` and `.. `
+    // into the parent directory
+    let trimmed = clean.trim_end_matches(['.', ' ']).len();
+    clean.truncate(trimmed);
+    if clean.is_empty() {
+        return None;
+    }
+
+    let stem = clean.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem.trim_end()))
+    {
+        clean.insert(0, '_');
+    }
+
+    Some(String::from(truncate(&clean, MAX_COMPONENT_LEN)))
+}
+
+/// Sanitizes every component of `path`. A path left without components
+/// becomes `_`.
+pub fn sanitize_path(path: &[String]) -> Vec<String> {
+    let clean: Vec<String> = path.iter().filter_map(|c| sanitize_component(c)).collect();
+    if clean.is_empty() {
+        vec![String::from("_")]
+    } else {
+        clean
+    }
+}
+
+/// `name (n).ext` for the `n`th attempt at a unique name, shortening `name`
+/// to keep it within `MAX_COMPONENT_LEN`.
+fn numbered(name: &str, n: usize) -> String {
+    let suffix = format!(" ({})", n);
+    let (stem, ext) = match name.rfind('.') {
+        // an extension too long to keep is treated as part of the stem
+        Some(dot) if dot > 0 && name.len() - dot + suffix.len() < MAX_COMPONENT_LEN => {
+            name.split_at(dot)
+        }
+        _ => (name, ""),
+    };
+    let stem = truncate(stem, MAX_COMPONENT_LEN - suffix.len() - ext.len());
+    format!("{}{}{}", stem, suffix, ext)
+}
+
+/// Case-insensitive file systems treat these as the same path.
+fn key(path: &[String]) -> String {
+    path.join("/").to_lowercase()
+}
+
+/// Renames `path[i]` to the first `name (n)` that is not `taken`. The search
+/// goes on from the last `n` tried for the same name, so many paths sharing
+/// a name do not each start over at 1.
+fn rename(
+    path: &mut [String],
+    i: usize,
+    next: &mut HashMap<String, usize>,
+    taken: impl Fn(&str) -> bool,
+) {
+    let n = next.entry(key(&path[..=i])).or_insert(1);
+    let original = path[i].clone();
+    loop {
+        path[i] = numbered(&original, *n);
+        *n += 1;
+        if !taken(&key(&path[..=i])) {
+            break;
+        }
+    }
+}
+
+/// Renames paths that would land on the same file, including ones that only
+/// differ in case, and files that clash with another file's directory. The
+/// first path to claim a name keeps it. Empty paths become `_`, as in
+/// `sanitize_path`.
+pub fn dedupe_paths(paths: &mut [Vec<String>]) {
+    let mut files = HashSet::new();
+    let mut dirs = HashSet::new();
+    let mut next = HashMap::new();
+    // directories renamed to get out of a file's way, so later paths in the
+    // same directory end up next to each other
+    let mut renamed_dirs: HashMap<String, String> = HashMap::new();
+
+    for path in paths.iter_mut() {
+        if path.is_empty() {
+            path.push(String::from("_"));
+        }
+        // a directory of this path where an earlier path put a file
+        for i in 0..path.len() - 1 {
+            let dir = key(&path[..=i]);
+            if let Some(name) = renamed_dirs.get(&dir) {
+                path[i] = name.clone();
+            } else if files.contains(&dir) {
+                rename(path, i, &mut next, |key| files.contains(key));
+                renamed_dirs.insert(dir, path[i].clone());
+            }
+        }
+
+        let last = path.len() - 1;
+        if files.contains(&key(path)) || dirs.contains(&key(path)) {
+            rename(path, last, &mut next, |key| {
+                files.contains(key) || dirs.contains(key)
+            });
+        }
+
+        for i in 0..last {
+            dirs.insert(key(&path[..=i]));
+        }
+        files.insert(key(path));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(components: &[&str]) -> Vec<String> {
+        components.iter().map(|c| String::from(*c)).collect()
+    }
+
+    #[test]
+    fn test_parent_and_current_directory() {
+        assert_eq!(
+            sanitize_path(&path(&["..", "..", "etc", "passwd"])),
+            ["etc", "passwd"]
+        );
+        assert_eq!(sanitize_path(&path(&["a", ".", "b"])), ["a", "b"]);
+        assert_eq!(sanitize_path(&path(&["a", "", "b"])), ["a", "b"]);
+        assert_eq!(sanitize_path(&path(&[".. ", "..."])), ["_"]);
+        assert_eq!(sanitize_path(&path(&[".hidden"])), [".hidden"]);
+    }
+
+    #[test]
+    fn test_separators_and_absolute_paths() {
+        assert_eq!(sanitize_path(&path(&["/etc/passwd"])), ["_etc_passwd"]);
+        assert_eq!(sanitize_path(&path(&["/", "etc"])), ["_", "etc"]);
+        assert_eq!(sanitize_path(&path(&["..\\..\\x"])), [".._.._x"]);
+        assert_eq!(
+            sanitize_path(&path(&["\\\\server", "share"])),
+            ["__server", "share"]
+        );
+    }
+
+    #[test]
+    fn test_drive_letters() {
+        assert_eq!(sanitize_path(&path(&["C:", "Windows"])), ["C_", "Windows"]);
+        assert_eq!(sanitize_path(&path(&["C:\\Windows"])), ["C__Windows"]);
+        assert_eq!(
+            sanitize_component("file.txt:stream"),
+            Some(String::from("file.txt_stream"))
+        );
+    }
+
+    #[test]
+    fn test_control_characters() {
+        assert_eq!(sanitize_component("a\0b"), Some(String::from("a_b")));
+        assert_eq!(
+            sanitize_component("new\nline"),
+            Some(String::from("new_line"))
+        );
+        assert_eq!(sanitize_component("what?*"), Some(String::from("what__")));
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        assert_eq!(sanitize_component("CON"), Some(String::from("_CON")));
+        assert_eq!(
+            sanitize_component("nul.txt"),
+            Some(String::from("_nul.txt"))
+        );
+        assert_eq!(
+            sanitize_component("Com1 .tar.gz"),
+            Some(String::from("_Com1 .tar.gz"))
+        );
+        assert_eq!(sanitize_component("aux "), Some(String::from("_aux")));
+        assert_eq!(sanitize_component("CONSOLE"), Some(String::from("CONSOLE")));
+        assert_eq!(sanitize_component("COM10"), Some(String::from("COM10")));
+    }
+
+    #[test]
+    fn test_long_names() {
+        let long = "é".repeat(200);
+        let clean = sanitize_component(&long).unwrap();
+        assert_eq!(clean.len(), 254);
+        assert!(long.starts_with(&clean));
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let mut paths = vec![
+            path(&["a.txt"]),
+            path(&["A.TXT"]),
+            path(&["a.txt"]),
+            path(&["dir", "x"]),
+            // a file where there is already a directory
+            path(&["dir"]),
+            path(&["file"]),
+            // a directory where there is already a file
+            path(&["file", "y"]),
+            path(&["file", "z"]),
+            path(&["noext"]),
+            path(&["noext"]),
+        ];
+        dedupe_paths(&mut paths);
+        assert_eq!(
+            paths,
+            vec![
+                path(&["a.txt"]),
+                path(&["A (1).TXT"]),
+                path(&["a (2).txt"]),
+                path(&["dir", "x"]),
+                path(&["dir (1)"]),
+                path(&["file"]),
+                path(&["file (1)", "y"]),
+                path(&["file (1)", "z"]),
+                path(&["noext"]),
+                path(&["noext (1)"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedupe_empty() {
+        let mut paths = vec![vec![], path(&["_"]), vec![]];
+        dedupe_paths(&mut paths);
+        assert_eq!(
+            paths,
+            vec![path(&["_"]), path(&["_ (1)"]), path(&["_ (2)"])]
+        );
+    }
+
+    #[test]
+    fn test_dedupe_many() {
+        let mut paths = vec![path(&["a"]); 10_000];
+        // already taken by name, so skipped when numbering
+        paths.insert(1, path(&["a (1)"]));
+        dedupe_paths(&mut paths);
+        assert_eq!(paths[2], ["a (2)"]);
+        assert_eq!(paths[10_000], ["a (10000)"]);
+    }
+
+    #[test]
+    fn test_dedupe_long_names() {
+        let long = format!("{}.txt", "é".repeat(125));
+        let mut paths = vec![path(&[&long]), path(&[&long])];
+        // an extension that leaves no room for the stem
+        let long_ext = format!("a.{}", "x".repeat(252));
+        paths.extend([path(&[&long_ext]), path(&[&long_ext])]);
+        dedupe_paths(&mut paths);
+
+        assert!(paths[1][0].len() <= MAX_COMPONENT_LEN);
+        assert_eq!(paths[1][0], format!("{} (1).txt", "é".repeat(123)));
+        assert_eq!(paths[3][0].len(), MAX_COMPONENT_LEN);
+        assert!(paths[3][0].ends_with("x (1)"));
+    }
+}
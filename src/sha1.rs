@@ -1,103 +1,209 @@
-pub fn sha1(input: &[u8]) -> Vec<u8> {
-    let mut h0: u32 = 0x67452301;
-    let mut h1: u32 = 0xEFCDAB89;
-    let mut h2: u32 = 0x98BADCFE;
-    let mut h3: u32 = 0x10325476;
-    let mut h4: u32 = 0xC3D2E1F0;
+#[cfg(target_arch = "x86_64")]
+mod x86;
 
-    let bytes_to_pad_to = 64;
+const H: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
 
-    let mut input = input.to_vec();
+/// Incremental SHA-1: feed data with `update` as it arrives, then `finalize`.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    /// Bytes of the current, incomplete 64-byte block.
+    block: [u8; 64],
+    block_len: usize,
+    /// Total message length in bytes.
+    len: u64,
+    /// Whether to use the SHA extensions of the CPU.
+    accelerated: bool,
+}
 
-    let original_length_in_bits = input.len() * 8;
+impl Default for Sha1 {
+    fn default() -> Self {
+        Sha1::new()
+    }
+}
 
-    input.push(0x80);
+impl Sha1 {
+    pub fn new() -> Self {
+        Sha1 {
+            accelerated: hardware_support(),
+            ..Sha1::portable()
+        }
+    }
 
-    let ml_bytes = input.len() as i32;
+    /// A hasher that never uses hardware acceleration, for tests and
+    /// benchmarks.
+    pub fn portable() -> Self {
+        Sha1 {
+            state: H,
+            block: [0; 64],
+            block_len: 0,
+            len: 0,
+            accelerated: false,
+        }
+    }
 
-    let pad_to_add = 56 - (ml_bytes % bytes_to_pad_to);
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
 
-    input.resize(input.len() + pad_to_add as usize, 0x00);
+        // top up a partially filled block first
+        if self.block_len > 0 {
+            let take = data.len().min(64 - self.block_len);
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < 64 {
+                return;
+            }
+            compress(&mut self.state, &self.block, self.accelerated);
+            self.block_len = 0;
+        }
 
-    let original_len = ((original_length_in_bits) as u64).to_be_bytes();
+        // then hash whole blocks straight from the input
+        let whole = data.len() - data.len() % 64;
+        compress(&mut self.state, &data[..whole], self.accelerated);
 
-    input.extend(original_len);
+        let rest = &data[whole..];
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
 
-    // break message into 512-bit chunks
+    pub fn finalize(mut self) -> [u8; 20] {
+        let original_length_in_bits = self.len.wrapping_mul(8);
 
-    let mut chunks: Vec<u32> = vec![];
+        // the message is followed by a single 1 bit, zeros up to 56 bytes into
+        // a block and the 64-bit length, spilling into an extra block if the
+        // 0x80 byte leaves fewer than 8 bytes for the length
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+        if self.block_len + 1 > 56 {
+            compress(&mut self.state, &self.block, self.accelerated);
+            self.block.fill(0);
+        }
+        self.block[56..].copy_from_slice(&original_length_in_bits.to_be_bytes());
+        compress(&mut self.state, &self.block, self.accelerated);
 
-    for i in 0..input.len() / 4 {
-        chunks.push(u32::from_be_bytes(
-            input[i * 4..(i + 1) * 4].try_into().unwrap(),
-        ));
+        let mut res = [0; 20];
+        for (chunk, h) in res.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&h.to_be_bytes());
+        }
+        res
     }
+}
 
-    // for each 512 chunk
-    for i in 0..chunks.len() / 16 {
-        let ch = &mut chunks[i * 16..(i + 1) * 16]; // 16 words
-        let mut ch = ch.to_vec();
-        for j in 16..80 {
-            ch.push((ch[j - 3] ^ ch[j - 8] ^ ch[j - 14] ^ ch[j - 16]).rotate_left(1));
-        }
-        let mut a = h0;
-        let mut b = h1;
-        let mut c = h2;
-        let mut d = h3;
-        let mut e = h4;
-
-        for j in 0..80 {
-            let f: u32;
-            let k: u32;
-            if j <= 19 {
-                f = (b & c) | ((!b) & d);
-                k = 0x5a827999;
-            } else if (20 <= j) && (j <= 39) {
-                f = b ^ c ^ d;
-                k = 0x6ed9eba1;
-            } else if (40 <= j) && (j <= 59) {
-                f = (b & c) | (b & d) | (c & d);
-                k = 0x8f1bbcdc;
-            } else {
-                f = b ^ c ^ d;
-                k = 0xca62c1d6;
-            }
+/// Whether `Sha1::new` picks a hardware accelerated implementation.
+pub fn hardware_support() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return x86::is_supported();
+    #[cfg(not(target_arch = "x86_64"))]
+    false
+}
 
-            let temp = a
-                .rotate_left(5)
-                .wrapping_add(f)
-                .wrapping_add(e)
-                .wrapping_add(k)
-                .wrapping_add(ch[j]);
-
-            e = d;
-            d = c;
-            c = b.rotate_left(30);
-            b = a;
-            a = temp;
-        }
+pub fn sha1(input: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(input);
+    hasher.finalize()
+}
+
+/// Runs the compression function over every 64-byte block of `blocks`,
+/// using the SHA extensions when the CPU has them.
+fn compress(state: &mut [u32; 5], blocks: &[u8], accelerated: bool) {
+    #[cfg(target_arch = "x86_64")]
+    if accelerated {
+        // SAFETY: `accelerated` is only set when `x86::is_supported()`.
+        unsafe { x86::compress(state, blocks) };
+        return;
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = accelerated;
+    compress_portable(state, blocks);
+}
+
+#[inline(always)]
+fn ch(b: u32, c: u32, d: u32) -> u32 {
+    d ^ (b & (c ^ d))
+}
+
+#[inline(always)]
+fn parity(b: u32, c: u32, d: u32) -> u32 {
+    b ^ c ^ d
+}
 
-        h0 = h0.wrapping_add(a);
-        h1 = h1.wrapping_add(b);
-        h2 = h2.wrapping_add(c);
-        h3 = h3.wrapping_add(d);
-        h4 = h4.wrapping_add(e);
+#[inline(always)]
+fn maj(b: u32, c: u32, d: u32) -> u32 {
+    (b & c) | (d & (b | c))
+}
+
+/// Message word `i`, computed in place in a rolling 16-word window.
+#[inline(always)]
+fn word(w: &mut [u32; 16], i: usize) -> u32 {
+    if i >= 16 {
+        w[i & 15] =
+            (w[(i + 13) & 15] ^ w[(i + 8) & 15] ^ w[(i + 2) & 15] ^ w[i & 15]).rotate_left(1);
     }
+    w[i & 15]
+}
+
+fn compress_portable(state: &mut [u32; 5], blocks: &[u8]) {
+    for block in blocks.chunks_exact(64) {
+        let mut w = [0u32; 16];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
 
-    let mut res: Vec<u8> = vec![];
+        // One round, with the variables renamed instead of shifted.
+        macro_rules! round {
+            ($f:ident, $k:expr, $i:expr, $a:ident, $b:ident, $c:ident, $d:ident, $e:ident) => {
+                $e = $e
+                    .wrapping_add($a.rotate_left(5))
+                    .wrapping_add($f($b, $c, $d))
+                    .wrapping_add($k)
+                    .wrapping_add(word(&mut w, $i));
+                $b = $b.rotate_left(30);
+            };
+        }
+        // Five rounds starting at `$i`, after which the names line up again.
+        macro_rules! rounds5 {
+            ($f:ident, $k:expr, $i:expr) => {
+                round!($f, $k, $i, a, b, c, d, e);
+                round!($f, $k, $i + 1, e, a, b, c, d);
+                round!($f, $k, $i + 2, d, e, a, b, c);
+                round!($f, $k, $i + 3, c, d, e, a, b);
+                round!($f, $k, $i + 4, b, c, d, e, a);
+            };
+        }
 
-    res.extend(h0.to_be_bytes());
-    res.extend(h1.to_be_bytes());
-    res.extend(h2.to_be_bytes());
-    res.extend(h3.to_be_bytes());
-    res.extend(h4.to_be_bytes());
+        rounds5!(ch, 0x5a827999, 0);
+        rounds5!(ch, 0x5a827999, 5);
+        rounds5!(ch, 0x5a827999, 10);
+        rounds5!(ch, 0x5a827999, 15);
+        rounds5!(parity, 0x6ed9eba1, 20);
+        rounds5!(parity, 0x6ed9eba1, 25);
+        rounds5!(parity, 0x6ed9eba1, 30);
+        rounds5!(parity, 0x6ed9eba1, 35);
+        rounds5!(maj, 0x8f1bbcdc, 40);
+        rounds5!(maj, 0x8f1bbcdc, 45);
+        rounds5!(maj, 0x8f1bbcdc, 50);
+        rounds5!(maj, 0x8f1bbcdc, 55);
+        rounds5!(parity, 0xca62c1d6, 60);
+        rounds5!(parity, 0xca62c1d6, 65);
+        rounds5!(parity, 0xca62c1d6, 70);
+        rounds5!(parity, 0xca62c1d6, 75);
 
-    res
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+        state[4] = state[4].wrapping_add(e);
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::encoding::hex_encode as hex;
+
     #[test]
     fn test_sha1() {
         let cases = [
@@ -114,11 +220,105 @@ mod test {
 
         for (input, output) in cases.into_iter() {
             let bytes = sha1(input.as_bytes());
-            let out: String = bytes
-                .iter()
-                .map(|b| format!("{:02x}", b).to_string())
-                .collect();
-            assert_eq!(out, output);
+            assert_eq!(hex(&bytes), output);
+        }
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let input: Vec<u8> = (0..1000u32).map(|i| (i * 7 + 3) as u8).collect();
+        let expected = sha1(&input);
+
+        for chunk_size in [1, 3, 63, 64, 65, 127, 500] {
+            let mut hasher = Sha1::new();
+            for chunk in input.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_million_a() {
+        let mut hasher = Sha1::new();
+        let block = [b'a'; 1000];
+        for _ in 0..1000 {
+            hasher.update(&block);
+        }
+        assert_eq!(
+            hex(&hasher.finalize()),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    #[test]
+    fn test_portable_matches_accelerated() {
+        if !hardware_support() {
+            return;
+        }
+        let input: Vec<u8> = (0..5000u32).map(|i| (i * 31 + i / 7) as u8).collect();
+        for len in [0, 1, 55, 56, 63, 64, 65, 128, 1000, 4096, 5000] {
+            let mut portable = Sha1::portable();
+            portable.update(&input[..len]);
+            let mut accelerated = Sha1::new();
+            accelerated.update(&input[..len]);
+            assert_eq!(
+                portable.finalize(),
+                accelerated.finalize(),
+                "length {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_portable_vectors() {
+        let mut hasher = Sha1::portable();
+        hasher.update(b"The quick brown fox jumps over the lazy dog");
+        assert_eq!(
+            hex(&hasher.finalize()),
+            "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"
+        );
+    }
+
+    /// Input of every length 0..=200, covering lengths that leave too little
+    /// room in the last block for the message length.
+    fn padding_input() -> Vec<u8> {
+        (0..200u32).map(|i| ((i * 31 + 7) % 256) as u8).collect()
+    }
+
+    #[test]
+    fn test_padding_boundaries() {
+        let input = padding_input();
+        let cases = [
+            (55, "749bbefb28edc4638b28b2b9a9e03ab9a4032b90"),
+            (56, "a5b6e9c29d201c774753ff8e7fb64931656f5e63"),
+            (57, "eb0737bed5451790722b2df351829ce117e3d9dd"),
+            (63, "d1a454409359fc372b4d22b3cea6488d6ba1be00"),
+            (64, "39a0d8b645ad85f1f976731ed112ac9455e28b78"),
+            (119, "562ecf8a430f8e1056e3619bae33628e9a1d0a4e"),
+            (120, "353f6d2bf0e91aa91b74a2e0b3f297510f7d825f"),
+        ];
+        for (len, digest) in cases {
+            assert_eq!(hex(&sha1(&input[..len])), digest, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_padding_all_lengths() {
+        let input = padding_input();
+        // the digests of every prefix, checked at once by hashing them all
+        for make in [Sha1::new, Sha1::portable] {
+            let mut hasher = make();
+            for len in 0..=input.len() {
+                let mut prefix = make();
+                prefix.update(&input[..len]);
+                hasher.update(&prefix.finalize());
+            }
+            assert_eq!(
+                hex(&hasher.finalize()),
+                "46521005cfa6bba4a9497b0bf09ae77fca55f3b1"
+            );
         }
     }
 }
@@ -0,0 +1,118 @@
+//! SHA-1 block compression with the x86 SHA extensions (SHA-NI), which do
+//! both the rounds and the message schedule in hardware.
+
+use std::arch::x86_64::*;
+
+pub fn is_supported() -> bool {
+    is_x86_feature_detected!("sha")
+        && is_x86_feature_detected!("sse2")
+        && is_x86_feature_detected!("ssse3")
+        && is_x86_feature_detected!("sse4.1")
+}
+
+/// Runs the compression function over every 64-byte block of `blocks`.
+///
+/// # Safety
+///
+/// The CPU must support the features checked by `is_supported`.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+pub unsafe fn compress(state: &mut [u32; 5], blocks: &[u8]) {
+    // reverses the bytes of the whole register: big endian words, and the
+    // word order sha1rnds4 expects
+    let mask = _mm_set_epi64x(0x0001_0203_0405_0607, 0x0809_0a0b_0c0d_0e0f);
+
+    let mut abcd = _mm_loadu_si128(state.as_ptr() as *const __m128i);
+    abcd = _mm_shuffle_epi32(abcd, 0x1b);
+    let mut e0 = _mm_set_epi32(state[4] as i32, 0, 0, 0);
+    let mut e1;
+
+    for block in blocks.chunks_exact(64) {
+        let abcd_save = abcd;
+        let e_save = e0;
+
+        let p = block.as_ptr() as *const __m128i;
+        let mut msg0 = _mm_shuffle_epi8(_mm_loadu_si128(p), mask);
+        let mut msg1 = _mm_shuffle_epi8(_mm_loadu_si128(p.add(1)), mask);
+        let mut msg2 = _mm_shuffle_epi8(_mm_loadu_si128(p.add(2)), mask);
+        let mut msg3 = _mm_shuffle_epi8(_mm_loadu_si128(p.add(3)), mask);
+
+        // Four rounds using message words `$m` (already scheduled), taking E
+        // from `$e` and saving the current ABCD into `$e_next` for the next
+        // four rounds.
+        macro_rules! rounds {
+            ($e:ident, $e_next:ident, $m:ident, $func:literal) => {
+                $e = _mm_sha1nexte_epu32($e, $m);
+                $e_next = abcd;
+                abcd = _mm_sha1rnds4_epu32(abcd, $e, $func);
+            };
+        }
+
+        // rounds 0-3
+        e0 = _mm_add_epi32(e0, msg0);
+        e1 = abcd;
+        abcd = _mm_sha1rnds4_epu32(abcd, e0, 0);
+
+        // rounds 4-7
+        rounds!(e1, e0, msg1, 0);
+        msg0 = _mm_sha1msg1_epu32(msg0, msg1);
+
+        // rounds 8-11
+        rounds!(e0, e1, msg2, 0);
+        msg1 = _mm_sha1msg1_epu32(msg1, msg2);
+        msg0 = _mm_xor_si128(msg0, msg2);
+
+        // From here on every group of four rounds also advances the message
+        // schedule: finish the next words, start the ones after.
+        macro_rules! rounds_scheduled {
+            ($e:ident, $e_next:ident, $m:ident, $m_finish:ident, $m_xor:ident, $m_start:ident, $func:literal) => {
+                $e = _mm_sha1nexte_epu32($e, $m);
+                $e_next = abcd;
+                $m_finish = _mm_sha1msg2_epu32($m_finish, $m);
+                abcd = _mm_sha1rnds4_epu32(abcd, $e, $func);
+                $m_start = _mm_sha1msg1_epu32($m_start, $m);
+                $m_xor = _mm_xor_si128($m_xor, $m);
+            };
+        }
+
+        // rounds 12-63
+        rounds_scheduled!(e1, e0, msg3, msg0, msg1, msg2, 0);
+        rounds_scheduled!(e0, e1, msg0, msg1, msg2, msg3, 0);
+        rounds_scheduled!(e1, e0, msg1, msg2, msg3, msg0, 1);
+        rounds_scheduled!(e0, e1, msg2, msg3, msg0, msg1, 1);
+        rounds_scheduled!(e1, e0, msg3, msg0, msg1, msg2, 1);
+        rounds_scheduled!(e0, e1, msg0, msg1, msg2, msg3, 1);
+        rounds_scheduled!(e1, e0, msg1, msg2, msg3, msg0, 1);
+        rounds_scheduled!(e0, e1, msg2, msg3, msg0, msg1, 2);
+        rounds_scheduled!(e1, e0, msg3, msg0, msg1, msg2, 2);
+        rounds_scheduled!(e0, e1, msg0, msg1, msg2, msg3, 2);
+        rounds_scheduled!(e1, e0, msg1, msg2, msg3, msg0, 2);
+        rounds_scheduled!(e0, e1, msg2, msg3, msg0, msg1, 2);
+        rounds_scheduled!(e1, e0, msg3, msg0, msg1, msg2, 3);
+
+        // rounds 64-67
+        rounds_scheduled!(e0, e1, msg0, msg1, msg2, msg3, 3);
+
+        // rounds 68-71
+        e1 = _mm_sha1nexte_epu32(e1, msg1);
+        e0 = abcd;
+        msg2 = _mm_sha1msg2_epu32(msg2, msg1);
+        abcd = _mm_sha1rnds4_epu32(abcd, e1, 3);
+        msg3 = _mm_xor_si128(msg3, msg1);
+
+        // rounds 72-75
+        e0 = _mm_sha1nexte_epu32(e0, msg2);
+        e1 = abcd;
+        msg3 = _mm_sha1msg2_epu32(msg3, msg2);
+        abcd = _mm_sha1rnds4_epu32(abcd, e0, 3);
+
+        // rounds 76-79
+        rounds!(e1, e0, msg3, 3);
+
+        e0 = _mm_sha1nexte_epu32(e0, e_save);
+        abcd = _mm_add_epi32(abcd, abcd_save);
+    }
+
+    abcd = _mm_shuffle_epi32(abcd, 0x1b);
+    _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, abcd);
+    state[4] = _mm_extract_epi32(e0, 3) as u32;
+}
@@ -0,0 +1,340 @@
+//! SHA-256 as used by BitTorrent v2 (BEP 52), and the merkle trees built
+//! from it.
+
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Size of the leaves of a v2 merkle tree.
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+/// Incremental SHA-256: feed data with `update` as it arrives, then
+/// `finalize`.
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    /// Bytes of the current, incomplete 64-byte block.
+    block: [u8; 64],
+    block_len: usize,
+    /// Total message length in bytes.
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256::new()
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            state: H,
+            block: [0; 64],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        // top up a partially filled block first
+        if self.block_len > 0 {
+            let take = data.len().min(64 - self.block_len);
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len < 64 {
+                return;
+            }
+            compress(&mut self.state, &self.block);
+            self.block_len = 0;
+        }
+
+        // then hash whole blocks straight from the input
+        let whole = data.len() - data.len() % 64;
+        compress(&mut self.state, &data[..whole]);
+
+        let rest = &data[whole..];
+        self.block[..rest.len()].copy_from_slice(rest);
+        self.block_len = rest.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 32] {
+        let original_length_in_bits = self.len.wrapping_mul(8);
+
+        // same padding as SHA-1: a 1 bit, zeros and the 64-bit length,
+        // spilling into an extra block when the length does not fit
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+        if self.block_len + 1 > 56 {
+            compress(&mut self.state, &self.block);
+            self.block.fill(0);
+        }
+        self.block[56..].copy_from_slice(&original_length_in_bits.to_be_bytes());
+        compress(&mut self.state, &self.block);
+
+        let mut res = [0; 32];
+        for (chunk, h) in res.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&h.to_be_bytes());
+        }
+        res
+    }
+}
+
+pub fn sha256(input: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(input);
+    hasher.finalize()
+}
+
+/// Runs the compression function over every 64-byte block of `blocks`.
+fn compress(state: &mut [u32; 8], blocks: &[u8]) {
+    for block in blocks.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+
+        for (k, w) in K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = g ^ (e & (f ^ g));
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(*k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) | (c & (a | b));
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+/// Root of the binary merkle tree over `leaves`, padded with zero hashes up
+/// to the next power of two. A single leaf is its own root.
+///
+/// Panics if there are no leaves.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    merkle_root_padded(leaves, [0; 32])
+}
+
+fn merkle_root_padded(leaves: &[[u8; 32]], pad: [u8; 32]) -> [u8; 32] {
+    assert!(!leaves.is_empty(), "a merkle tree needs at least one leaf");
+
+    let mut layer = leaves.to_vec();
+    layer.resize(leaves.len().next_power_of_two(), pad);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(&pair[0]);
+                hasher.update(&pair[1]);
+                hasher.finalize()
+            })
+            .collect();
+    }
+    layer[0]
+}
+
+/// The v2 `pieces root` of a file: the merkle root over the hashes of its
+/// 16 KiB blocks, the last of which may be shorter. Empty files have none.
+pub fn pieces_root(data: &[u8]) -> Option<[u8; 32]> {
+    if data.is_empty() {
+        return None;
+    }
+    let leaves: Vec<[u8; 32]> = data.chunks(BLOCK_SIZE).map(sha256).collect();
+    Some(merkle_root(&leaves))
+}
+
+/// The hash of a piece in a file's piece layer: the root of the subtree of
+/// `piece_length / BLOCK_SIZE` leaves, where leaves past the end of `data`
+/// are zero.
+///
+/// Panics if `piece_length` is not a power of two of at least `BLOCK_SIZE`,
+/// or `data` is longer than a piece.
+pub fn piece_hash(data: &[u8], piece_length: usize) -> [u8; 32] {
+    assert!(
+        piece_length.is_power_of_two() && piece_length >= BLOCK_SIZE,
+        "invalid v2 piece length {}",
+        piece_length
+    );
+    assert!(data.len() <= piece_length, "data is longer than a piece");
+
+    let mut leaves: Vec<[u8; 32]> = data.chunks(BLOCK_SIZE).map(sha256).collect();
+    leaves.resize(piece_length / BLOCK_SIZE, [0; 32]);
+    merkle_root(&leaves)
+}
+
+/// The `pieces root` of a file rebuilt from its piece layer. Pieces past the
+/// end of the file hash like pieces of zero leaves, so this matches
+/// `pieces_root` over the file's data.
+///
+/// Panics if the layer is empty.
+pub fn layer_root(layer: &[[u8; 32]], piece_length: usize) -> [u8; 32] {
+    merkle_root_padded(layer, piece_hash(&[], piece_length))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::encoding::hex_encode as hex;
+
+    #[test]
+    fn test_sha256() {
+        let cases = [
+            (
+                "",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                "abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+            (
+                "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+                "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1",
+            ),
+        ];
+
+        for (input, output) in cases {
+            assert_eq!(hex(&sha256(input.as_bytes())), output, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_million_a() {
+        let mut hasher = Sha256::new();
+        let block = [b'a'; 1000];
+        for _ in 0..1000 {
+            hasher.update(&block);
+        }
+        assert_eq!(
+            hex(&hasher.finalize()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let input: Vec<u8> = (0..1000u32).map(|i| (i * 7 + 3) as u8).collect();
+        let expected = sha256(&input);
+
+        for chunk_size in [1, 3, 55, 56, 63, 64, 65, 500] {
+            let mut hasher = Sha256::new();
+            for chunk in input.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    fn node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut pair = left.to_vec();
+        pair.extend(right);
+        sha256(&pair)
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let leaves: Vec<[u8; 32]> = (0..3u8).map(|i| sha256(&[i])).collect();
+        let zero = [0; 32];
+
+        assert_eq!(merkle_root(&leaves[..1]), leaves[0]);
+        assert_eq!(merkle_root(&leaves[..2]), node(&leaves[0], &leaves[1]));
+        assert_eq!(
+            merkle_root(&leaves),
+            node(&node(&leaves[0], &leaves[1]), &node(&leaves[2], &zero))
+        );
+    }
+
+    #[test]
+    fn test_pieces_root() {
+        assert_eq!(pieces_root(b""), None);
+        assert_eq!(pieces_root(b"abc"), Some(sha256(b"abc")));
+
+        let data = vec![7u8; BLOCK_SIZE * 2 + 10];
+        let blocks: Vec<[u8; 32]> = data.chunks(BLOCK_SIZE).map(sha256).collect();
+        assert_eq!(
+            pieces_root(&data),
+            Some(node(
+                &node(&blocks[0], &blocks[1]),
+                &node(&blocks[2], &[0; 32])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_piece_layer() {
+        let piece_length = 2 * BLOCK_SIZE;
+        let zero = [0; 32];
+
+        let piece = vec![3u8; BLOCK_SIZE + 1];
+        let blocks: Vec<[u8; 32]> = piece.chunks(BLOCK_SIZE).map(sha256).collect();
+        assert_eq!(
+            piece_hash(&piece, piece_length),
+            node(&blocks[0], &blocks[1])
+        );
+        assert_eq!(
+            piece_hash(&piece[..10], piece_length),
+            node(&sha256(&piece[..10]), &zero)
+        );
+
+        // 3 pieces, padded to 4 in the layer, and 5 or 6 blocks padded to 8
+        // in the tree over the whole file
+        for len in [5 * BLOCK_SIZE + 10, 6 * BLOCK_SIZE, 4 * BLOCK_SIZE + 1] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 253) as u8).collect();
+            let layer: Vec<[u8; 32]> = data
+                .chunks(piece_length)
+                .map(|piece| piece_hash(piece, piece_length))
+                .collect();
+            assert_eq!(layer.len(), 3);
+            assert_eq!(
+                Some(layer_root(&layer, piece_length)),
+                pieces_root(&data),
+                "length {}",
+                len
+            );
+        }
+    }
+}
@@ -4,13 +4,19 @@ use std::{
     path::Path,
 };
 
-use crate::torrent_file::TorrentFile;
+use crate::{
+    magnet::{MagnetLink, PendingMagnet},
+    torrent_file::{TorrentFile, TorrentFileError, TorrentId},
+};
 use home::home_dir;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppState {
     pub torrents: Vec<TorrentFile>,
+    /// Magnet links whose metadata has not been fetched yet.
+    #[serde(default)]
+    pub magnets: Vec<PendingMagnet>,
 }
 
 impl AppState {
@@ -29,6 +35,37 @@ impl AppState {
         serde_json::from_str(&content).unwrap()
     }
 
+    /// An id not used by any torrent or magnet link.
+    pub fn next_id(&self) -> TorrentId {
+        let torrents = self.torrents.iter().map(|t| t.id);
+        let magnets = self.magnets.iter().map(|m| m.id);
+        torrents.chain(magnets).max().map_or(1, |id| id + 1)
+    }
+
+    pub fn add_magnet(&mut self, link: MagnetLink) -> TorrentId {
+        let id = self.next_id();
+        self.magnets.push(PendingMagnet { id, link });
+        id
+    }
+
+    /// Turns magnet link `id` into a torrent once its `info` dictionary has
+    /// been fetched, keeping the id. Returns false if there is no such
+    /// magnet link; on an error it stays pending.
+    pub fn complete_magnet(
+        &mut self,
+        id: TorrentId,
+        info: &[u8],
+    ) -> Result<bool, TorrentFileError> {
+        let Some(index) = self.magnets.iter().position(|m| m.id == id) else {
+            return Ok(false);
+        };
+        let mut torrent = TorrentFile::from_metadata(&self.magnets[index].link, info)?;
+        torrent.id = id;
+        self.magnets.remove(index);
+        self.torrents.push(torrent);
+        Ok(true)
+    }
+
     pub fn save(self) -> io::Result<()> {
         fs::write(
             format!(
@@ -49,7 +86,10 @@ fn create_app_data_dir_all() -> io::Result<()> {
 }
 
 fn create_app_state_file() -> io::Result<()> {
-    let default = AppState { torrents: vec![] };
+    let default = AppState {
+        torrents: vec![],
+        magnets: vec![],
+    };
     if !Path::new(&format!(
         "{}/.local/share/torrent.rs/state.json",
         home_dir().unwrap().display()
@@ -67,3 +107,39 @@ fn create_app_state_file() -> io::Result<()> {
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::torrent_file::InfoHash;
+
+    #[test]
+    fn test_complete_magnet() {
+        let mut info = b"d6:lengthi1e4:name1:a12:piece lengthi4e6:pieces20:".to_vec();
+        info.extend([0; 20]);
+        info.push(b'e');
+        let link = MagnetLink::parse(&format!(
+            "magnet:?xt=urn:btih:{}&dn=a",
+            InfoHash::of(&info).to_hex()
+        ))
+        .unwrap();
+
+        let mut state = AppState {
+            torrents: vec![],
+            magnets: vec![],
+        };
+        assert_eq!(state.next_id(), 1);
+        assert_eq!(state.add_magnet(link.clone()), 1);
+        assert_eq!(state.add_magnet(link), 2);
+
+        assert!(state.complete_magnet(1, b"de").is_err());
+        assert_eq!(state.magnets.len(), 2);
+
+        assert!(state.complete_magnet(1, &info).unwrap());
+        assert!(!state.complete_magnet(1, &info).unwrap());
+        assert_eq!(state.magnets.len(), 1);
+        assert_eq!(state.torrents[0].id, 1);
+        assert_eq!(state.torrents[0].info.name, "a");
+        assert_eq!(state.next_id(), 3);
+    }
+}
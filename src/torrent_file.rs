@@ -1,95 +1,1151 @@
-use std::path::Path;
+use std::{collections::HashMap, fmt, fs, io, path::Path};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    bencode::{Bencode, BencodeType},
+    bencode::{BencodeDict, BencodeError, BencodeRef, BencodeType, DecodeOptions, PathError},
+    encoding::{hex_encode, percent_encode},
+    magnet::MagnetLink,
+    sanitize::{dedupe_paths, sanitize_component, sanitize_path},
+    sha1::sha1,
+    sha256::{layer_root, piece_hash, pieces_root, sha256, BLOCK_SIZE},
     state::AppState,
 };
 pub type TorrentId = u32;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TorrentFileInfoFile {
-    lenght: u32,
-    path: Vec<String>,
+/// Why a .torrent file could not be turned into a `TorrentFile`.
+#[derive(Debug)]
+pub enum TorrentFileError {
+    Io(io::Error),
+    /// The file is not valid bencode.
+    Bencode(BencodeError),
+    /// A required key is missing, or a key has the wrong type.
+    Field(PathError),
+    /// A key is present but its value makes no sense, e.g. a negative length.
+    Invalid {
+        path: String,
+        reason: String,
+    },
+    /// Metadata from a peer does not hash to the info-hash it was requested
+    /// for.
+    InfoHashMismatch {
+        expected: InfoHash,
+        actual: InfoHash,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct TorrentFileInfo {
-    files: Vec<TorrentFileInfoFile>,
+impl fmt::Display for TorrentFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorrentFileError::Io(err) => write!(f, "cannot read torrent file: {}", err),
+            TorrentFileError::Bencode(err) => write!(f, "torrent file is not bencoded: {}", err),
+            TorrentFileError::Field(err) => write!(f, "invalid torrent file: {}", err),
+            TorrentFileError::Invalid { path, reason } => {
+                write!(f, "invalid torrent file: `{}` {}", path, reason)
+            }
+            TorrentFileError::InfoHashMismatch { expected, actual } => {
+                write!(f, "metadata hashes to {} instead of {}", actual, expected)
+            }
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl std::error::Error for TorrentFileError {}
+
+impl From<io::Error> for TorrentFileError {
+    fn from(err: io::Error) -> Self {
+        TorrentFileError::Io(err)
+    }
+}
+
+impl From<BencodeError> for TorrentFileError {
+    fn from(err: BencodeError) -> Self {
+        TorrentFileError::Bencode(err)
+    }
+}
+
+impl From<PathError> for TorrentFileError {
+    fn from(err: PathError) -> Self {
+        TorrentFileError::Field(err)
+    }
+}
+
+fn invalid(path: &str, reason: impl Into<String>) -> TorrentFileError {
+    TorrentFileError::Invalid {
+        path: String::from(path),
+        reason: reason.into(),
+    }
+}
+
+/// Turns a missing key into `None` while keeping type errors.
+fn optional<T>(value: Result<T, PathError>) -> Result<Option<T>, PathError> {
+    match value {
+        Ok(value) => Ok(Some(value)),
+        Err(PathError::NotFound { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Text fields are not always UTF-8 in the wild (see `encoding`), so they are
+/// decoded lossily rather than rejected.
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn length(root: &BencodeType, path: &str) -> Result<u64, TorrentFileError> {
+    u64::try_from(root.path_int(path)?).map_err(|_| invalid(path, "is negative"))
+}
+
+/// SHA-1 of the bencoded `info` dictionary, which identifies a torrent to
+/// trackers and peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InfoHash(pub [u8; 20]);
+
+impl InfoHash {
+    /// Hashes the exact bytes of an `info` dictionary. Re-encoding a parsed
+    /// dictionary is not enough: torrents with unsorted keys exist.
+    pub fn of(info: &[u8]) -> Self {
+        InfoHash(sha1(info))
+    }
+
+    /// Whether `info` is the `info` dictionary known by this hash: by its
+    /// SHA-1, or by its truncated SHA-256 for v2 torrents.
+    pub fn matches(&self, info: &[u8]) -> bool {
+        InfoHash::of(info) == *self || InfoHashV2::of(info).truncated() == *self
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex_encode(&self.0)
+    }
+
+    /// Percent-encoded form for the `info_hash` parameter of HTTP trackers.
+    pub fn to_url_encoded(&self) -> String {
+        percent_encode(&self.0)
+    }
+}
+
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// SHA-256 of the bencoded `info` dictionary of a v2 or hybrid torrent
+/// (BEP 52).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InfoHashV2(pub [u8; 32]);
+
+impl InfoHashV2 {
+    pub fn of(info: &[u8]) -> Self {
+        InfoHashV2(sha256(info))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex_encode(&self.0)
+    }
+
+    /// The first 20 bytes, which stand in for a v1 info-hash wherever only
+    /// 20 bytes fit, such as tracker announces.
+    pub fn truncated(&self) -> InfoHash {
+        InfoHash(self.0[..20].try_into().unwrap())
+    }
+}
+
+impl fmt::Display for InfoHashV2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TorrentFileInfoFile {
+    pub length: u64,
+    /// Sanitized, so it is safe to join onto the download directory.
+    pub path: Vec<String>,
+    /// File attributes (BEP 47), one letter each: `p` padding, `x`
+    /// executable, `h` hidden, `l` symlink.
+    #[serde(default)]
+    pub attr: String,
+}
+
+impl TorrentFileInfoFile {
+    /// Padding files only exist to align the next file to a piece boundary;
+    /// their content is zeros and they are not written to disk.
+    pub fn is_padding(&self) -> bool {
+        self.attr.contains('p')
+    }
+}
+
+/// A file of the v2 `file tree`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TorrentFileInfoV2File {
+    /// Sanitized like the v1 paths. A single-file torrent has one file whose
+    /// path is its name.
+    pub path: Vec<String>,
+    pub length: u64,
+    /// Root of the merkle tree over the 16 KiB blocks of the file; `None`
+    /// for empty files.
+    pub pieces_root: Option<[u8; 32]>,
+    /// Hash of every piece, from `piece layers`, for files longer than a
+    /// piece. Empty for other files, and until the layer is known.
+    pub piece_layer: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TorrentFileInfo {
+    /// File name of a single-file torrent, or directory name of a multi-file
+    /// one. Sanitized like the file paths.
+    pub name: String,
+    pub piece_length: u64,
+    /// SHA-1 hash of every piece; empty for v2-only torrents.
+    pub pieces: Vec<[u8; 20]>,
+    /// Only announce to the listed trackers (BEP 27).
+    pub private: bool,
+    /// Size of the single file; `None` for multi-file and v2-only torrents.
+    pub length: Option<u64>,
+    /// Files of a multi-file torrent; empty for single-file and v2-only
+    /// torrents.
+    pub files: Vec<TorrentFileInfoFile>,
+    /// Files of a v2 or hybrid torrent (`meta version` 2); `None` for v1.
+    #[serde(default)]
+    pub file_tree: Option<Vec<TorrentFileInfoV2File>>,
+}
+
+impl TorrentFileInfo {
+    /// Combined size of all files.
+    pub fn total_length(&self) -> u64 {
+        match (self.length, &self.file_tree) {
+            (Some(length), _) => length,
+            (None, Some(tree)) if !self.has_v1() => tree.iter().map(|file| file.length).sum(),
+            (None, _) => self.files.iter().map(|file| file.length).sum(),
+        }
+    }
+
+    /// Whether the torrent has v1 data: true for v1 and hybrid torrents.
+    pub fn has_v1(&self) -> bool {
+        self.file_tree.is_none() || self.length.is_some() || !self.files.is_empty()
+    }
+
+    /// Checks piece `piece` of v2 file `file` against the file's piece layer,
+    /// or against its `pieces root` when the file fits in one piece. Pieces
+    /// of v2 files start at the beginning of the file.
+    pub fn verify_v2_piece(&self, file: usize, piece: usize, data: &[u8]) -> bool {
+        let Some(file) = self.file_tree.as_ref().and_then(|tree| tree.get(file)) else {
+            return false;
+        };
+        let Some(start) = (piece as u64).checked_mul(self.piece_length) else {
+            return false;
+        };
+        if start >= file.length || data.len() as u64 != (file.length - start).min(self.piece_length)
+        {
+            return false;
+        }
+
+        if file.length <= self.piece_length {
+            return pieces_root(data) == file.pieces_root;
+        }
+        file.piece_layer
+            .get(piece)
+            .is_some_and(|hash| *hash == piece_hash(data, self.piece_length as usize))
+    }
+
+    /// Checks the whole content of v2 file `file` against its `pieces root`.
+    pub fn verify_v2_file(&self, file: usize, data: &[u8]) -> bool {
+        self.file_tree
+            .as_ref()
+            .and_then(|tree| tree.get(file))
+            .is_some_and(|file| {
+                data.len() as u64 == file.length && pieces_root(data) == file.pieces_root
+            })
+    }
+
+    fn from_bencode(root: &BencodeType) -> Result<Self, TorrentFileError> {
+        // `name.utf-8` and `path.utf-8` are what some clients write next to
+        // names in another encoding
+        let name = match optional(root.path_str("info.name\\.utf-8"))? {
+            Some(name) => String::from(name),
+            None => text(root.path_bytes("info.name")?),
+        };
+        let name = sanitize_component(&name).unwrap_or_else(|| String::from("_"));
+
+        let v2 = match optional(root.path_int("info.meta version"))? {
+            None | Some(1) => false,
+            Some(2) => true,
+            Some(_) => return Err(invalid("info.meta version", "is not 1 or 2")),
+        };
+        // hybrid torrents carry v1 data next to the v2 data
+        let has_v1 = !v2 || optional(root.path("info.pieces"))?.is_some();
+
+        let piece_length = length(root, "info.piece length")?;
+        if piece_length == 0 {
+            return Err(invalid("info.piece length", "is zero"));
+        }
+        if v2 && (!piece_length.is_power_of_two() || piece_length < BLOCK_SIZE as u64) {
+            return Err(invalid(
+                "info.piece length",
+                "is not a power of two of at least 16 KiB",
+            ));
+        }
+
+        let private = optional(root.path_int("info.private"))? == Some(1);
+        let file_tree = if v2 { Some(file_tree(root)?) } else { None };
+
+        if !has_v1 {
+            return Ok(TorrentFileInfo {
+                name,
+                piece_length,
+                pieces: vec![],
+                private,
+                length: None,
+                files: vec![],
+                file_tree,
+            });
+        }
+
+        let pieces = root.path_bytes("info.pieces")?;
+        if pieces.len() % 20 != 0 {
+            return Err(invalid(
+                "info.pieces",
+                format!("has {} bytes, not a multiple of 20", pieces.len()),
+            ));
+        }
+        let pieces = pieces
+            .chunks_exact(20)
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+
+        let (length, files) = match (
+            optional(root.path("info.length"))?,
+            optional(root.path_list("info.files"))?,
+        ) {
+            (Some(_), None) => (Some(length(root, "info.length")?), vec![]),
+            (None, Some(files)) => {
+                let mut files = (0..files.len())
+                    .map(|i| TorrentFileInfoFile::from_bencode(root, &format!("info.files.{}", i)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let mut paths: Vec<Vec<String>> = files
+                    .iter_mut()
+                    .map(|file| std::mem::take(&mut file.path))
+                    .collect();
+                dedupe_paths(&mut paths);
+                for (file, path) in files.iter_mut().zip(paths) {
+                    file.path = path;
+                }
+                (None, files)
+            }
+            (Some(_), Some(_)) => {
+                return Err(invalid("info", "has both `length` and `files`"));
+            }
+            (None, None) => return Err(invalid("info", "has neither `length` nor `files`")),
+        };
+
+        let info = TorrentFileInfo {
+            name,
+            piece_length,
+            pieces,
+            private,
+            length,
+            files,
+            file_tree,
+        };
+
+        let expected = info.total_length().div_ceil(piece_length);
+        if info.pieces.len() as u64 != expected {
+            return Err(invalid(
+                "info.pieces",
+                format!(
+                    "has {} hashes but the files need {}",
+                    info.pieces.len(),
+                    expected
+                ),
+            ));
+        }
+
+        if let Some(tree) = &info.file_tree {
+            let v1_files: Vec<(&[String], u64)> = match info.length {
+                Some(length) => vec![(std::slice::from_ref(&info.name), length)],
+                None => info
+                    .files
+                    .iter()
+                    .filter(|file| !file.is_padding())
+                    .map(|file| (file.path.as_slice(), file.length))
+                    .collect(),
+            };
+            let v2_files = tree.iter().map(|file| (file.path.as_slice(), file.length));
+            if !v1_files.into_iter().eq(v2_files) {
+                return Err(invalid(
+                    "info.file tree",
+                    "does not match the v1 files of a hybrid torrent",
+                ));
+            }
+        }
+        Ok(info)
+    }
+}
+
+/// Flattens the v2 `file tree`, where directories are dictionaries keyed by
+/// name and a file is a dictionary under the empty key.
+fn file_tree(root: &BencodeType) -> Result<Vec<TorrentFileInfoV2File>, TorrentFileError> {
+    let mut files = vec![];
+    walk_file_tree(
+        root.path_dict("info.file tree")?,
+        "info.file tree",
+        &mut vec![],
+        &mut files,
+    )?;
+    if files.is_empty() {
+        return Err(invalid("info.file tree", "has no files"));
+    }
+
+    let mut paths: Vec<Vec<String>> = files.iter().map(|file| sanitize_path(&file.path)).collect();
+    dedupe_paths(&mut paths);
+    for (file, path) in files.iter_mut().zip(paths) {
+        file.path = path;
+    }
+    Ok(files)
+}
+
+fn walk_file_tree(
+    dir: &BencodeDict,
+    path: &str,
+    components: &mut Vec<String>,
+    files: &mut Vec<TorrentFileInfoV2File>,
+) -> Result<(), TorrentFileError> {
+    for (name, node) in dir.iter() {
+        let node_path = format!("{}.{}", path, text(name));
+        let node = node
+            .as_dict()
+            .ok_or_else(|| invalid(&node_path, "is not a dictionary"))?;
+        components.push(text(name));
+
+        match node.get("") {
+            Some(file) => {
+                let file = file
+                    .as_dict()
+                    .ok_or_else(|| invalid(&node_path, "is not a file dictionary"))?;
+                let length = file
+                    .get("length")
+                    .and_then(BencodeType::as_int)
+                    .and_then(|length| u64::try_from(length).ok())
+                    .ok_or_else(|| invalid(&node_path, "has no valid `length`"))?;
+                let pieces_root = match file.get("pieces root") {
+                    Some(hash) => Some(
+                        hash.as_bytes()
+                            .and_then(|hash| hash.try_into().ok())
+                            .ok_or_else(|| {
+                                invalid(&node_path, "has a `pieces root` that is not 32 bytes")
+                            })?,
+                    ),
+                    None if length == 0 => None,
+                    None => return Err(invalid(&node_path, "has no `pieces root`")),
+                };
+                files.push(TorrentFileInfoV2File {
+                    path: components.clone(),
+                    length,
+                    pieces_root,
+                    piece_layer: vec![],
+                });
+            }
+            None => walk_file_tree(node, &node_path, components, files)?,
+        }
+        components.pop();
+    }
+    Ok(())
+}
+
+/// Moves the hashes in `piece layers`, keyed by `pieces root`, to the files
+/// they belong to, after checking them against the root.
+fn piece_layers(
+    root: &BencodeType,
+    piece_length: u64,
+    files: &mut [TorrentFileInfoV2File],
+) -> Result<(), TorrentFileError> {
+    let Some(layers) = optional(root.path_dict("piece layers"))? else {
+        return Ok(());
+    };
+    // one lookup per file, and torrents can have many of both
+    let layers: HashMap<&[u8], &BencodeType> = layers.iter().collect();
+
+    for file in files.iter_mut().filter(|file| file.length > piece_length) {
+        // files longer than a piece are not empty
+        let pieces_root = file.pieces_root.unwrap();
+        let Some(layer) = layers.get(&pieces_root[..]) else {
+            continue;
+        };
+        let name = file.path.join("/");
+        let layer = layer
+            .as_bytes()
+            .ok_or_else(|| invalid("piece layers", format!("of `{}` is not a string", name)))?;
+
+        let expected = file.length.div_ceil(piece_length) * 32;
+        if layer.len() as u64 != expected {
+            return Err(invalid(
+                "piece layers",
+                format!("of `{}` has {} bytes, not {}", name, layer.len(), expected),
+            ));
+        }
+        let hashes: Vec<[u8; 32]> = layer
+            .chunks_exact(32)
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+        if layer_root(&hashes, piece_length as usize) != pieces_root {
+            return Err(invalid(
+                "piece layers",
+                format!("of `{}` do not match its `pieces root`", name),
+            ));
+        }
+        file.piece_layer = hashes;
+    }
+    Ok(())
+}
+
+impl TorrentFileInfoFile {
+    fn from_bencode(root: &BencodeType, path: &str) -> Result<Self, TorrentFileError> {
+        let length = length(root, &format!("{}.length", path))?;
+
+        let utf8_path = format!("{}.path\\.utf-8", path);
+        let components_path = match optional(root.path_list(&utf8_path))? {
+            Some(_) => utf8_path,
+            None => format!("{}.path", path),
+        };
+        let components = root.path_list(&components_path)?;
+        if components.is_empty() {
+            return Err(invalid(&components_path, "is empty"));
+        }
+        let components = (0..components.len())
+            .map(|i| {
+                Ok(text(
+                    root.path_bytes(&format!("{}.{}", components_path, i))?,
+                ))
+            })
+            .collect::<Result<Vec<_>, PathError>>()?;
+
+        Ok(TorrentFileInfoFile {
+            length,
+            path: sanitize_path(&components),
+            attr: optional(root.path_bytes(&format!("{}.attr", path)))?
+                .map(text)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TorrentFile {
-    id: TorrentId,
-    announce: String,
-    creation_date: u32,
-    info: TorrentFileInfo,
-    name: String,
-    piece_length: u32,
-    pieces: Vec<u8>,
-}
-
-fn add_torrent(torrent_file_path: &String) {
-    todo!()
-    // let b = Bencode::from_file(Path::new(torrent_file_path)).unwrap();
-    //
-    // let root_dict = if let BencodeType::Dict(d) = b.node {
-    //     d
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let announce = if let BencodeType::Str(str) = root_dict.get("announce").unwrap() {
-    //     str
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let creation_date = if let BencodeType::Int(int) = root_dict.get("creation date").unwrap() {
-    //     int
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let info = if let BencodeType::Dict(d) = root_dict.get("info").unwrap() {
-    //     d
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let name = if let BencodeType::Str(d) = info.get("name").unwrap() {
-    //     d
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let piece_length = if let BencodeType::Int(int) = info.get("piece length").unwrap() {
-    //     int
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let pieces = if let BencodeType::Str(str) = info.get("pieces").unwrap() {
-    //     str
-    // } else {
-    //     panic!()
-    // };
-    //
-    // let mut state = AppState::load();
-    // state.torrents.push(TorrentFile {
-    //     announce: announce.iter().map(|c| *c as char).collect(),
-    //     creation_date: *creation_date as u32,
-    //     name: name.iter().map(|c| *c as char).collect(),
-    //     piece_length: *piece_length as u32,
-    //     pieces: pieces.to_owned(),
-    //     id: 123,
-    //     info: TorrentFileInfo {
-    //         files: vec![TorrentFileInfoFile {
-    //             lenght: 123,
-    //             path: vec![String::from("hello")],
-    //         }],
-    //     },
-    // });
-    // state.save();
+    /// Assigned when the torrent is added to the `AppState`; 0 until then.
+    pub id: TorrentId,
+    /// What trackers and peers know the torrent by: the SHA-1 info-hash, or
+    /// the truncated v2 one for v2-only torrents.
+    pub info_hash: InfoHash,
+    /// Set for v2 and hybrid torrents.
+    #[serde(default)]
+    pub info_hash_v2: Option<InfoHashV2>,
+    pub announce: Option<String>,
+    /// Tiers of tracker URLs (BEP 12).
+    pub announce_list: Vec<Vec<String>>,
+    /// Seconds since the Unix epoch.
+    pub creation_date: Option<i64>,
+    pub created_by: Option<String>,
+    pub comment: Option<String>,
+    /// Character set of the text fields, as declared by the creator.
+    pub encoding: Option<String>,
+    /// HTTP servers hosting the same files (`url-list`, BEP 19).
+    pub web_seeds: Vec<String>,
+    pub info: TorrentFileInfo,
+}
+
+impl TorrentFile {
+    /// Parses the contents of a .torrent file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TorrentFileError> {
+        // .torrent files are chosen by the user, and big ones easily exceed
+        // the limits meant for network input
+        let torrent = TorrentFile::parse(bytes, &DecodeOptions::trusted())?;
+
+        // peers can be asked for missing layers, but a .torrent file must
+        // carry them
+        let piece_length = torrent.info.piece_length;
+        if let Some(file) = torrent
+            .info
+            .file_tree
+            .iter()
+            .flatten()
+            .find(|file| file.length > piece_length && file.piece_layer.is_empty())
+        {
+            return Err(invalid(
+                "piece layers",
+                format!("has no hashes for `{}`", file.path.join("/")),
+            ));
+        }
+        Ok(torrent)
+    }
+
+    /// Builds the torrent of a magnet link from the `info` dictionary fetched
+    /// from peers (BEP 9), which must match the link's info-hash, v1 or
+    /// truncated v2, and its full v2 info-hash if it has one. Trackers and
+    /// web seeds come from the link.
+    ///
+    /// The hashes are those of the `info` dictionary, as for `from_bytes`,
+    /// not the link's: a hybrid torrent found through its v2 hash gets the
+    /// SHA-1 as `info_hash`, with the link's hash left in `info_hash_v2`.
+    pub fn from_metadata(link: &MagnetLink, info: &[u8]) -> Result<Self, TorrentFileError> {
+        let v2_mismatch = link
+            .info_hash_v2
+            .is_some_and(|hash| InfoHashV2::of(info) != hash);
+        if !link.info_hash.matches(info) || v2_mismatch {
+            return Err(TorrentFileError::InfoHashMismatch {
+                expected: link.info_hash,
+                actual: InfoHash::of(info),
+            });
+        }
+
+        let mut bytes = b"d4:info".to_vec();
+        bytes.extend(info);
+        bytes.push(b'e');
+        let mut torrent = TorrentFile::parse(&bytes, &DecodeOptions::default())?;
+
+        torrent.announce = link.trackers.first().cloned();
+        if link.trackers.len() > 1 {
+            // each tracker in its own tier, tried in the order of the link
+            torrent.announce_list = link.trackers.iter().map(|url| vec![url.clone()]).collect();
+        }
+        torrent.web_seeds = link.web_seeds.clone();
+        Ok(torrent)
+    }
+
+    fn parse(bytes: &[u8], options: &DecodeOptions) -> Result<Self, TorrentFileError> {
+        let parsed = BencodeRef::from_u8_with(bytes, options)?;
+        let root = parsed.to_bencode_type();
+        let mut info = TorrentFileInfo::from_bencode(&root)?;
+        let piece_length = info.piece_length;
+        if let Some(tree) = &mut info.file_tree {
+            piece_layers(&root, piece_length, tree)?;
+        }
+
+        // `from_bencode` made sure `info` is there
+        let info_bytes = parsed.get("info").unwrap().slice;
+        let info_hash_v2 = info.file_tree.is_some().then(|| InfoHashV2::of(info_bytes));
+        let info_hash = match info_hash_v2 {
+            Some(hash) if !info.has_v1() => hash.truncated(),
+            _ => InfoHash::of(info_bytes),
+        };
+
+        let announce_list = match optional(root.path_list("announce-list"))? {
+            Some(tiers) => (0..tiers.len())
+                .map(|tier| {
+                    let tier_path = format!("announce-list.{}", tier);
+                    let urls = root.path_list(&tier_path)?;
+                    (0..urls.len())
+                        .map(|url| Ok(text(root.path_bytes(&format!("{}.{}", tier_path, url))?)))
+                        .collect()
+                })
+                .collect::<Result<_, PathError>>()?,
+            None => vec![],
+        };
+
+        // either a single URL or a list of them
+        let web_seeds = match optional(root.path("url-list"))? {
+            Some(BencodeType::Str(url)) => vec![text(url)],
+            Some(BencodeType::List(urls)) => (0..urls.len())
+                .map(|i| Ok(text(root.path_bytes(&format!("url-list.{}", i))?)))
+                .collect::<Result<_, PathError>>()?,
+            Some(_) => return Err(invalid("url-list", "is not a string or a list")),
+            None => vec![],
+        };
+
+        Ok(TorrentFile {
+            id: 0,
+            info_hash,
+            info_hash_v2,
+            announce: optional(root.path_bytes("announce"))?.map(text),
+            announce_list,
+            creation_date: optional(root.path_int("creation date"))?,
+            created_by: optional(root.path_bytes("created by"))?.map(text),
+            comment: optional(root.path_bytes("comment"))?.map(text),
+            encoding: optional(root.path_bytes("encoding"))?.map(text),
+            web_seeds,
+            info,
+        })
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, TorrentFileError> {
+        TorrentFile::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Parses a .torrent file and starts tracking it, returning its new id.
+pub fn add_torrent(torrent_file_path: &str) -> Result<TorrentId, TorrentFileError> {
+    let mut torrent = TorrentFile::from_path(torrent_file_path)?;
+
+    let mut state = AppState::load();
+    torrent.id = state.next_id();
+    let id = torrent.id;
+    state.torrents.push(torrent);
+    state.save()?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{bencode::Bencode, file_layout::FileLayout};
+
+    fn single_file() -> Vec<u8> {
+        let mut input = b"d8:announce21:udp://tracker.test:8013:announce-listll21:udp://tracker.test:80el17:http://other.testee7:comment5:hello10:created by4:test13:creation datei1681726664e4:infod6:lengthi5e4:name5:a.txt12:piece lengthi4e6:pieces40:".to_vec();
+        input.extend([0xaa; 20]);
+        input.extend([0xbb; 20]);
+        input.extend(b"7:privatei1ee8:url-list22:http://seed.test/a.txte");
+        input
+    }
+
+    #[test]
+    fn test_single_file() {
+        let torrent = TorrentFile::from_bytes(&single_file()).unwrap();
+
+        assert_eq!(torrent.announce.as_deref(), Some("udp://tracker.test:80"));
+        assert_eq!(
+            torrent.announce_list,
+            vec![
+                vec![String::from("udp://tracker.test:80")],
+                vec![String::from("http://other.test")]
+            ]
+        );
+        assert_eq!(torrent.comment.as_deref(), Some("hello"));
+        assert_eq!(torrent.created_by.as_deref(), Some("test"));
+        assert_eq!(torrent.creation_date, Some(1681726664));
+        assert_eq!(torrent.encoding, None);
+        assert_eq!(
+            torrent.web_seeds,
+            vec![String::from("http://seed.test/a.txt")]
+        );
+        assert_eq!(torrent.info.name, "a.txt");
+        assert_eq!(torrent.info.piece_length, 4);
+        assert_eq!(torrent.info.pieces, vec![[0xaa; 20], [0xbb; 20]]);
+        assert!(torrent.info.private);
+        assert_eq!(torrent.info.length, Some(5));
+        assert!(torrent.info.files.is_empty());
+    }
+
+    #[test]
+    fn test_info_hash() {
+        let torrent = TorrentFile::from_bytes(&single_file()).unwrap();
+        assert_eq!(
+            torrent.info_hash.to_hex(),
+            "78b45df6afcb654a8d48bc01c245aff3f91eb54a"
+        );
+        assert_eq!(torrent.info_hash.to_string(), torrent.info_hash.to_hex());
+
+        // keys out of order: the hash must cover the bytes as they are, not
+        // a canonical re-encoding
+        let mut input = b"d4:infod12:piece lengthi4e6:lengthi1e4:name1:a6:pieces20:".to_vec();
+        input.extend([0; 20]);
+        input.extend(b"ee");
+        let info = &input[7..input.len() - 1];
+        let torrent = TorrentFile::from_bytes(&input).unwrap();
+        assert_eq!(torrent.info_hash, InfoHash::of(info));
+        assert_ne!(
+            torrent.info_hash,
+            InfoHash::of(&Bencode::from_u8(info).unwrap().node.encode())
+        );
+    }
+
+    #[test]
+    fn test_info_hash_url_encoded() {
+        let mut hash = [0u8; 20];
+        hash[..8].copy_from_slice(b"aZ09-._~");
+        hash[8..12].copy_from_slice(&[b' ', b'%', 0x12, 0xff]);
+        assert_eq!(
+            InfoHash(hash).to_url_encoded(),
+            format!("aZ09-._~%20%25%12%FF{}", "%00".repeat(8))
+        );
+    }
+
+    #[test]
+    fn test_multi_file() {
+        let mut input = b"d4:infod5:filesld6:lengthi3e4:pathl3:dir5:a.txteed4:attr1:x6:lengthi0e4:pathl5:b.txteee4:name4:root12:piece lengthi16384e6:pieces20:".to_vec();
+        input.extend([0xcc; 20]);
+        input.extend(b"ee");
+
+        let torrent = TorrentFile::from_bytes(&input).unwrap();
+        assert_eq!(torrent.announce, None);
+        assert!(torrent.announce_list.is_empty());
+        assert!(torrent.web_seeds.is_empty());
+        assert!(!torrent.info.private);
+        assert_eq!(torrent.info.length, None);
+        assert_eq!(
+            torrent.info.files,
+            vec![
+                TorrentFileInfoFile {
+                    length: 3,
+                    path: vec![String::from("dir"), String::from("a.txt")],
+                    attr: String::new(),
+                },
+                TorrentFileInfoFile {
+                    length: 0,
+                    path: vec![String::from("b.txt")],
+                    attr: String::from("x"),
+                },
+            ]
+        );
+        assert_eq!(torrent.info.total_length(), 3);
+    }
+
+    #[test]
+    fn test_sanitized_paths() {
+        let mut input = b"d4:infod5:filesl".to_vec();
+        input.extend(b"d6:lengthi1e4:pathl2:..2:..4:evilee");
+        input.extend(b"d6:lengthi1e4:pathl4:EVILee");
+        input.extend(b"d6:lengthi1e4:pathl3:\xe9t\xe9e10:path.utf-8l5:\xc3\xa9t\xc3\xa9ee");
+        input.extend(b"d6:lengthi1e4:pathl3:C:\\3:CONee");
+        input.extend(b"e4:name2:..12:piece lengthi4e6:pieces20:");
+        input.extend([0; 20]);
+        input.extend(b"ee");
+
+        let torrent = TorrentFile::from_bytes(&input).unwrap();
+        assert_eq!(torrent.info.name, "_");
+        let paths: Vec<String> = torrent
+            .info
+            .files
+            .iter()
+            .map(|file| file.path.join("/"))
+            .collect();
+        assert_eq!(paths, ["evil", "EVIL (1)", "\u{e9}t\u{e9}", "C__/_CON"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let input = single_file();
+        let replace = |from: &str, to: &str| {
+            let from = from.as_bytes();
+            let at = input.windows(from.len()).position(|w| w == from).unwrap();
+            let mut out = input[..at].to_vec();
+            out.extend(to.as_bytes());
+            out.extend(&input[at + from.len()..]);
+            TorrentFile::from_bytes(&out).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            replace("4:name5:a.txt", ""),
+            "invalid torrent file: `info.name` not found"
+        );
+        assert_eq!(
+            replace("7:comment5:hello", "7:commenti1e"),
+            "invalid torrent file: `comment` is not a string"
+        );
+        assert_eq!(
+            replace("lengthi5e", "lengthi-5e"),
+            "invalid torrent file: `info.length` is negative"
+        );
+        assert_eq!(
+            replace("lengthi5e", "lengthi9e"),
+            "invalid torrent file: `info.pieces` has 2 hashes but the files need 3"
+        );
+        assert_eq!(
+            replace("piece lengthi4e", "piece lengthi0e"),
+            "invalid torrent file: `info.piece length` is zero"
+        );
+        assert_eq!(
+            replace("6:lengthi5e", ""),
+            "invalid torrent file: `info` has neither `length` nor `files`"
+        );
+        assert!(matches!(
+            TorrentFile::from_bytes(&input[..100]),
+            Err(TorrentFileError::Bencode(BencodeError::UnexpectedEof {
+                offset: 100
+            }))
+        ));
+        assert!(TorrentFile::from_bytes(b"i1e").is_err());
+        assert!(matches!(
+            TorrentFile::from_path("/nonexistent/file.torrent"),
+            Err(TorrentFileError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let bytes = single_file();
+        let find = |needle: &[u8]| bytes.windows(needle.len()).position(|w| w == needle);
+        let info = &bytes[find(b"4:info").unwrap() + 6..find(b"8:url-list").unwrap()];
+        let link = MagnetLink::parse(&format!(
+            "magnet:?xt=urn:btih:{}&tr=udp://a.test:80&tr=udp://b.test:80&ws=http://seed.test/",
+            InfoHash::of(info).to_hex()
+        ))
+        .unwrap();
+
+        let torrent = TorrentFile::from_metadata(&link, info).unwrap();
+        assert_eq!(torrent, {
+            let mut expected = TorrentFile::from_bytes(&bytes).unwrap();
+            expected.announce = Some(String::from("udp://a.test:80"));
+            expected.announce_list = vec![
+                vec![String::from("udp://a.test:80")],
+                vec![String::from("udp://b.test:80")],
+            ];
+            expected.creation_date = None;
+            expected.created_by = None;
+            expected.comment = None;
+            expected.web_seeds = vec![String::from("http://seed.test/")];
+            expected
+        });
+
+        let mut tampered = info.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            TorrentFile::from_metadata(&link, &tampered),
+            Err(TorrentFileError::InfoHashMismatch { expected, .. }) if expected == link.info_hash
+        ));
+
+        // the v1 hash matches, but a v2 hash in the link has to as well
+        let mut hybrid_link = link.clone();
+        hybrid_link.info_hash_v2 = Some(InfoHashV2([0; 32]));
+        assert!(matches!(
+            TorrentFile::from_metadata(&hybrid_link, info),
+            Err(TorrentFileError::InfoHashMismatch { .. })
+        ));
+    }
+
+    fn dict(entries: Vec<(&str, BencodeType)>) -> BencodeType {
+        let mut dict = BencodeDict::new();
+        for (key, value) in entries {
+            dict.insert(key, value);
+        }
+        BencodeType::Dict(dict)
+    }
+
+    fn int(value: usize) -> BencodeType {
+        BencodeType::Int(value as i64)
+    }
+
+    fn string(value: impl Into<Vec<u8>>) -> BencodeType {
+        BencodeType::Str(value.into())
+    }
+
+    const V2_PIECE: usize = BLOCK_SIZE;
+
+    fn content(len: usize, seed: usize) -> Vec<u8> {
+        (0..len).map(|i| ((i * 13 + seed) % 251) as u8).collect()
+    }
+
+    fn v2_file(data: &[u8]) -> BencodeType {
+        let mut entries = vec![("length", int(data.len()))];
+        if let Some(root) = pieces_root(data) {
+            entries.push(("pieces root", string(root)));
+        }
+        dict(vec![("", dict(entries))])
+    }
+
+    fn layer(data: &[u8]) -> Vec<u8> {
+        data.chunks(V2_PIECE)
+            .flat_map(|piece| piece_hash(piece, V2_PIECE))
+            .collect()
+    }
+
+    /// `big.bin` of 3 pieces, `dir/small.txt` and an empty file.
+    fn v2_info(big: &[u8], small: &[u8]) -> Vec<(&'static str, BencodeType)> {
+        vec![
+            (
+                "file tree",
+                dict(vec![
+                    ("big.bin", v2_file(big)),
+                    ("dir", dict(vec![("small.txt", v2_file(small))])),
+                    ("empty", v2_file(b"")),
+                ]),
+            ),
+            ("meta version", int(2)),
+            ("name", string("v2")),
+            ("piece length", int(V2_PIECE)),
+        ]
+    }
+
+    fn v2_torrent(info: BencodeType, big: &[u8]) -> Vec<u8> {
+        let mut layers = BencodeDict::new();
+        layers.insert(pieces_root(big).unwrap(), string(layer(big)));
+        dict(vec![
+            ("info", info),
+            ("piece layers", BencodeType::Dict(layers)),
+        ])
+        .encode()
+    }
+
+    #[test]
+    fn test_v2() {
+        let (big, small) = (content(2 * V2_PIECE + 100, 1), content(100, 2));
+        let info = dict(v2_info(&big, &small));
+        let torrent = TorrentFile::from_bytes(&v2_torrent(info.clone(), &big)).unwrap();
+
+        let hash = InfoHashV2::of(&info.encode());
+        assert_eq!(torrent.info_hash_v2, Some(hash));
+        assert_eq!(torrent.info_hash, hash.truncated());
+        assert_eq!(&torrent.info_hash.0[..], &hash.0[..20]);
+
+        let info = &torrent.info;
+        assert!(!info.has_v1());
+        assert!(info.pieces.is_empty());
+        assert_eq!(info.total_length(), big.len() as u64 + 100);
+        let tree = info.file_tree.as_ref().unwrap();
+        let paths: Vec<String> = tree.iter().map(|file| file.path.join("/")).collect();
+        assert_eq!(paths, ["big.bin", "dir/small.txt", "empty"]);
+        assert_eq!(tree[0].piece_layer.len(), 3);
+        assert!(tree[1].piece_layer.is_empty());
+        assert_eq!(tree[2].pieces_root, None);
+
+        for (index, piece) in big.chunks(V2_PIECE).enumerate() {
+            assert!(info.verify_v2_piece(0, index, piece));
+            let mut bad = piece.to_vec();
+            bad[0] ^= 1;
+            assert!(!info.verify_v2_piece(0, index, &bad));
+        }
+        assert!(!info.verify_v2_piece(0, 1, &big[..V2_PIECE]));
+        assert!(!info.verify_v2_piece(0, 3, &[]));
+        assert!(info.verify_v2_piece(1, 0, &small));
+        assert!(!info.verify_v2_piece(1, 0, &small[1..]));
+        assert!(info.verify_v2_file(0, &big));
+        assert!(info.verify_v2_file(2, b""));
+        assert!(!info.verify_v2_file(1, &big));
+
+        // indices from peers may be anything
+        assert!(!info.verify_v2_piece(3, 0, &small));
+        assert!(!info.verify_v2_piece(usize::MAX, 0, &small));
+        assert!(!info.verify_v2_piece(0, usize::MAX, &big[..V2_PIECE]));
+        assert!(!info.verify_v2_file(3, b""));
+        assert!(!info.verify_v2_file(usize::MAX, &small));
+
+        // 3 pieces of big.bin, then one for small.txt
+        let layout = FileLayout::from_torrent(&torrent);
+        assert_eq!(layout.piece_count(), 4);
+        assert_eq!(layout.files()[0].path, ["v2", "big.bin"]);
+    }
+
+    #[test]
+    fn test_hybrid() {
+        let (big, small) = (content(2 * V2_PIECE + 100, 1), content(100, 2));
+        let padding = 3 * V2_PIECE - big.len();
+        let mut stream = big.clone();
+        stream.resize(3 * V2_PIECE, 0);
+        stream.extend(&small);
+        let pieces: Vec<u8> = stream.chunks(V2_PIECE).flat_map(sha1).collect();
+
+        let v1_file = |length: usize, path: &[&str], attr: &str| {
+            let path = path.iter().map(|c| string(*c)).collect();
+            let mut entries = vec![("length", int(length)), ("path", BencodeType::List(path))];
+            if !attr.is_empty() {
+                entries.push(("attr", string(attr)));
+            }
+            dict(entries)
+        };
+        let hybrid = |small_name: &str| {
+            let mut info = v2_info(&big, &small);
+            info.push((
+                "files",
+                BencodeType::List(vec![
+                    v1_file(big.len(), &["big.bin"], ""),
+                    v1_file(padding, &[".pad", "9"], "p"),
+                    v1_file(100, &["dir", small_name], ""),
+                    v1_file(0, &["empty"], ""),
+                ]),
+            ));
+            info.push(("pieces", string(pieces.clone())));
+            dict(info)
+        };
+
+        let info = hybrid("small.txt");
+        let torrent = TorrentFile::from_bytes(&v2_torrent(info.clone(), &big)).unwrap();
+        assert!(torrent.info.has_v1());
+        assert_eq!(torrent.info_hash, InfoHash::of(&info.encode()));
+        assert_eq!(torrent.info_hash_v2, Some(InfoHashV2::of(&info.encode())));
+        assert_eq!(torrent.info.pieces.len(), 4);
+        assert_eq!(torrent.info.total_length(), stream.len() as u64);
+        assert_eq!(FileLayout::from_torrent(&torrent).piece_count(), 4);
+
+        let err = TorrentFile::from_bytes(&v2_torrent(hybrid("other.txt"), &big)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid torrent file: `info.file tree` does not match the v1 files of a hybrid torrent"
+        );
+
+        // magnet links of hybrid torrents may carry either hash, but the
+        // torrent is known by the same ones as when loaded from the file
+        let v2 = torrent.info_hash_v2.unwrap();
+        for uri in [
+            format!("magnet:?xt=urn:btih:{}", torrent.info_hash),
+            format!("magnet:?xt=urn:btih:{}", v2.truncated()),
+            format!("magnet:?xt=urn:btmh:1220{}", v2),
+        ] {
+            let link = MagnetLink::parse(&uri).unwrap();
+            let from_peer = TorrentFile::from_metadata(&link, &info.encode()).unwrap();
+            assert_eq!(from_peer.info_hash, torrent.info_hash);
+            assert_eq!(from_peer.info_hash_v2, Some(v2));
+            assert_eq!(from_peer.to_magnet().info_hash, torrent.info_hash);
+            assert_eq!(from_peer.info, {
+                let mut info = torrent.info.clone();
+                info.file_tree.as_mut().unwrap()[0].piece_layer.clear();
+                info
+            });
+        }
+    }
+
+    #[test]
+    fn test_v2_errors() {
+        let (big, small) = (content(2 * V2_PIECE + 100, 1), content(100, 2));
+        let error = |info: Vec<(&str, BencodeType)>, bytes: Option<Vec<u8>>| {
+            let bytes = bytes.unwrap_or_else(|| v2_torrent(dict(info), &big));
+            TorrentFile::from_bytes(&bytes).unwrap_err().to_string()
+        };
+        let with = |key: &'static str, value: BencodeType| {
+            let mut info = v2_info(&big, &small);
+            info.retain(|(k, _)| *k != key);
+            info.push((key, value));
+            info
+        };
+
+        assert_eq!(
+            error(with("meta version", int(3)), None),
+            "invalid torrent file: `info.meta version` is not 1 or 2"
+        );
+        assert_eq!(
+            error(with("piece length", int(3 * BLOCK_SIZE)), None),
+            "invalid torrent file: `info.piece length` is not a power of two of at least 16 KiB"
+        );
+        assert_eq!(
+            error(with("file tree", dict(vec![])), None),
+            "invalid torrent file: `info.file tree` has no files"
+        );
+        assert_eq!(
+            error(
+                with(
+                    "file tree",
+                    dict(vec![(
+                        "a",
+                        dict(vec![("", dict(vec![("length", int(1))]))])
+                    )])
+                ),
+                None
+            ),
+            "invalid torrent file: `info.file tree.a` has no `pieces root`"
+        );
+        assert_eq!(
+            error(with("file tree", dict(vec![("a", int(1))])), None),
+            "invalid torrent file: `info.file tree.a` is not a dictionary"
+        );
+
+        let info = dict(v2_info(&big, &small));
+        let without_layers = dict(vec![("info", info.clone())]).encode();
+        assert_eq!(
+            error(vec![], Some(without_layers)),
+            "invalid torrent file: `piece layers` has no hashes for `big.bin`"
+        );
+
+        let mut layers = BencodeDict::new();
+        let mut bad_layer = layer(&big);
+        bad_layer[0] ^= 1;
+        layers.insert(pieces_root(&big).unwrap(), string(bad_layer));
+        let bad = dict(vec![
+            ("info", info),
+            ("piece layers", BencodeType::Dict(layers)),
+        ])
+        .encode();
+        assert_eq!(
+            error(vec![], Some(bad)),
+            "invalid torrent file: `piece layers` of `big.bin` do not match its `pieces root`"
+        );
+    }
 }
@@ -1,46 +1,658 @@
+//! Client side of the UDP tracker protocol (BEP 15).
+
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
 use rand::random;
+use tokio::{
+    net::{lookup_host, ToSocketAddrs, UdpSocket},
+    time::{timeout, Instant},
+};
+
+use crate::torrent_file::InfoHash;
+
+/// Magic number that starts every connect request.
+pub const PROTOCOL_ID: u64 = 0x41727101980;
 
-const PROTOCOL_ID: u64 = 0x41727101980;
+/// Largest UDP payload; responses are never longer.
+const MAX_PACKET_LEN: usize = 65_507;
 
+/// BEP 15 stops doubling the wait after this many retransmissions.
+const MAX_BACKOFF_EXPONENT: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 enum Action {
     Connect = 0,
     Announce = 1,
+    Error = 3,
 }
 
-enum TrackerProtocol {
-    Http,
-    Udp,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AnnounceEvent {
+    /// A regular announce while downloading or seeding.
+    None = 0,
+    Completed = 1,
+    Started = 2,
+    Stopped = 3,
 }
 
-struct TrackerConnection {
-    protocol: TrackerProtocol,
-    location: String,
-    port: Option<u32>,
-    connection_id: Option<u64>,
+#[derive(Debug)]
+pub enum TrackerError {
+    Io(io::Error),
+    /// The URL given is not a `udp://host:port` tracker URL.
+    InvalidUrl(String),
+    /// The tracker answered with an error message (action 3).
+    Failure(String),
+    /// A response to one of our requests that does not follow the protocol.
+    InvalidResponse(String),
+    /// No response after sending a request `attempts` times.
+    Timeout {
+        attempts: u32,
+    },
 }
 
-impl TrackerConnection {
-    fn new(url: String) {}
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackerError::Io(err) => write!(f, "cannot reach tracker: {}", err),
+            TrackerError::InvalidUrl(url) => write!(f, "not a UDP tracker URL: {}", url),
+            TrackerError::Failure(message) => write!(f, "tracker error: {}", message),
+            TrackerError::InvalidResponse(reason) => {
+                write!(f, "invalid tracker response: {}", reason)
+            }
+            TrackerError::Timeout { attempts } => {
+                write!(f, "tracker did not respond to {} attempts", attempts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrackerError {}
+
+impl From<io::Error> for TrackerError {
+    fn from(err: io::Error) -> Self {
+        TrackerError::Io(err)
+    }
+}
 
-    fn connect(self) {
-        let mut rng = rand::thread_rng();
+fn invalid_response(reason: impl Into<String>) -> TrackerError {
+    TrackerError::InvalidResponse(reason.into())
+}
 
+#[derive(Debug, Clone)]
+pub struct AnnounceRequest {
+    pub info_hash: InfoHash,
+    pub peer_id: [u8; 20],
+    pub downloaded: u64,
+    pub left: u64,
+    pub uploaded: u64,
+    pub event: AnnounceEvent,
+    /// Lets the tracker recognize us when our IP address changes.
+    pub key: u32,
+    /// How many peers we would like; `None` leaves it to the tracker.
+    pub num_want: Option<u32>,
+    /// Port we accept peer connections on.
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    /// Seconds to wait before the next regular announce.
+    pub interval: u32,
+    pub leechers: u32,
+    pub seeders: u32,
+    pub peers: Vec<SocketAddr>,
+}
+
+/// Timing of `UdpTracker`. The defaults are the ones from BEP 15; tests
+/// scale them down.
+#[derive(Debug, Clone)]
+pub struct UdpTrackerOptions {
+    /// How long to wait for the first response. Every retransmission waits
+    /// twice as long as the one before, up to `base_timeout * 2^8`.
+    pub base_timeout: Duration,
+    /// Retransmissions after the first request before giving up. Connect
+    /// requests made on the way to an announce count against the same
+    /// budget.
+    pub max_retries: u32,
+    /// How long a connection id may be used after it was received.
+    pub connection_id_lifetime: Duration,
+}
+
+impl Default for UdpTrackerOptions {
+    fn default() -> Self {
+        UdpTrackerOptions {
+            base_timeout: Duration::from_secs(15),
+            max_retries: 8,
+            connection_id_lifetime: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A tracker reached over UDP. The connection id from the connect exchange
+/// is kept and reused for announces until it expires.
+#[derive(Debug)]
+pub struct UdpTracker {
+    socket: UdpSocket,
+    options: UdpTrackerOptions,
+    /// The connection id and when it was received.
+    connection_id: Option<(u64, Instant)>,
+    buf: Vec<u8>,
+}
+
+impl UdpTracker {
+    /// Opens a socket to the tracker at `addr`, without sending anything.
+    pub async fn new(addr: impl ToSocketAddrs) -> Result<Self, TrackerError> {
+        UdpTracker::with_options(addr, UdpTrackerOptions::default()).await
+    }
+
+    pub async fn with_options(
+        addr: impl ToSocketAddrs,
+        options: UdpTrackerOptions,
+    ) -> Result<Self, TrackerError> {
+        let addr = lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for tracker"))?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(UdpTracker {
+            socket,
+            options,
+            connection_id: None,
+            buf: vec![0; MAX_PACKET_LEN],
+        })
+    }
+
+    /// Opens a socket to the tracker of a `udp://host:port/...` URL.
+    pub async fn from_url(url: &str) -> Result<Self, TrackerError> {
+        let invalid_url = || TrackerError::InvalidUrl(String::from(url));
+        let rest = url.strip_prefix("udp://").ok_or_else(invalid_url)?;
+        let host_port = rest.split('/').next().unwrap_or_default();
+        if !host_port.contains(':') {
+            return Err(invalid_url());
+        }
+        UdpTracker::new(host_port).await
+    }
+
+    /// Exchanges a connection id with the tracker, which announces have to
+    /// carry.
+    pub async fn connect(&mut self) -> Result<u64, TrackerError> {
+        self.connect_counting(&mut 0).await
+    }
+
+    /// `connect`, counting the requests it sends in `attempt`.
+    async fn connect_counting(&mut self, attempt: &mut u64) -> Result<u64, TrackerError> {
+        let transaction_id: u32 = random();
+        let mut packet = Vec::with_capacity(16);
+        packet.extend(PROTOCOL_ID.to_be_bytes());
+        packet.extend((Action::Connect as u32).to_be_bytes());
+        packet.extend(transaction_id.to_be_bytes());
+
+        while *attempt <= u64::from(self.options.max_retries) {
+            let sent = self.attempt(&packet, transaction_id, *attempt).await?;
+            *attempt += 1;
+            let Some(len) = sent else {
+                continue;
+            };
+            let body = self.body(len, Action::Connect)?;
+            let connection_id = body
+                .get(..8)
+                .ok_or_else(|| invalid_response("connect response is too short"))?;
+            let connection_id = u64::from_be_bytes(connection_id.try_into().unwrap());
+            self.connection_id = Some((connection_id, Instant::now()));
+            return Ok(connection_id);
+        }
+        Err(self.timeout())
+    }
+
+    /// The cached connection id, if it has not expired.
+    fn cached_connection_id(&self) -> Option<u64> {
+        self.connection_id
+            .filter(|(_, received)| received.elapsed() < self.options.connection_id_lifetime)
+            .map(|(connection_id, _)| connection_id)
+    }
+
+    /// Announces to the tracker, connecting first if needed, and returns the
+    /// peers it sent.
+    pub async fn announce(
+        &mut self,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse, TrackerError> {
+        let ipv6 = self.socket.peer_addr()?.is_ipv6();
+        // kept for every retransmission, so a late answer to an earlier one
+        // is still accepted
         let transaction_id: u32 = random();
+        let mut attempt = 0;
+
+        loop {
+            // retransmissions can outlast the connection id
+            let connection_id = match self.cached_connection_id() {
+                Some(connection_id) => connection_id,
+                None => self.connect_counting(&mut attempt).await?,
+            };
+            if attempt > u64::from(self.options.max_retries) {
+                return Err(self.timeout());
+            }
+
+            let mut packet = Vec::with_capacity(98);
+            packet.extend(connection_id.to_be_bytes());
+            packet.extend((Action::Announce as u32).to_be_bytes());
+            packet.extend(transaction_id.to_be_bytes());
+            packet.extend(request.info_hash.0);
+            packet.extend(request.peer_id);
+            packet.extend(request.downloaded.to_be_bytes());
+            packet.extend(request.left.to_be_bytes());
+            packet.extend(request.uploaded.to_be_bytes());
+            packet.extend((request.event as u32).to_be_bytes());
+            // IP address: 0 asks the tracker to use the one the packet came from
+            packet.extend(0u32.to_be_bytes());
+            packet.extend(request.key.to_be_bytes());
+            // -1 for the default
+            packet.extend(request.num_want.unwrap_or(u32::MAX).to_be_bytes());
+            packet.extend(request.port.to_be_bytes());
+
+            let sent = self.attempt(&packet, transaction_id, attempt).await?;
+            attempt += 1;
+            if let Some(len) = sent {
+                return parse_announce_response(self.body(len, Action::Announce)?, ipv6);
+            }
+        }
+    }
+
+    /// Sends `packet` and waits for the response to it as long as `backoff`
+    /// says for the `attempt`th request. Returns the length of the response,
+    /// or `None` on a timeout. Packets with another transaction id, e.g.
+    /// answers to requests that were given up on, are skipped.
+    async fn attempt(
+        &mut self,
+        packet: &[u8],
+        transaction_id: u32,
+        attempt: u64,
+    ) -> Result<Option<usize>, TrackerError> {
+        self.socket.send(packet).await?;
+
+        let wait = backoff(self.options.base_timeout, attempt);
+        let receive = async {
+            loop {
+                let len = self.socket.recv(&mut self.buf).await?;
+                if len >= 8 && self.buf[4..8] == transaction_id.to_be_bytes() {
+                    return Ok::<_, io::Error>(len);
+                }
+            }
+        };
+        match timeout(wait, receive).await {
+            Ok(len) => Ok(Some(len?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// What follows the action and transaction id of the response in the
+    /// buffer, if it has the expected action.
+    fn body(&self, len: usize, action: Action) -> Result<&[u8], TrackerError> {
+        let received = u32::from_be_bytes(self.buf[..4].try_into().unwrap());
+        let body = &self.buf[8..len];
+        if received == Action::Error as u32 {
+            return Err(TrackerError::Failure(
+                String::from_utf8_lossy(body).into_owned(),
+            ));
+        }
+        if received != action as u32 {
+            return Err(invalid_response(format!(
+                "expected action {} but got {}",
+                action as u32, received
+            )));
+        }
+        Ok(body)
     }
 
-    fn announce(self) {}
+    fn timeout(&self) -> TrackerError {
+        TrackerError::Timeout {
+            attempts: self.options.max_retries.saturating_add(1),
+        }
+    }
+}
+
+/// How long to wait for the response to the `attempt`th request:
+/// `base_timeout * 2^n`, where n stops growing at 8. Saturates rather than
+/// overflowing.
+fn backoff(base_timeout: Duration, attempt: u64) -> Duration {
+    let exponent = attempt.min(u64::from(MAX_BACKOFF_EXPONENT)) as u32;
+    2u32.checked_pow(exponent)
+        .and_then(|factor| base_timeout.checked_mul(factor))
+        .unwrap_or(Duration::MAX)
 }
 
-struct ConnectPayload {}
+/// Parses an announce response after the action and transaction id. Peers
+/// are 6 bytes each, or 18 when the tracker is reached over IPv6.
+fn parse_announce_response(body: &[u8], ipv6: bool) -> Result<AnnounceResponse, TrackerError> {
+    if body.len() < 12 {
+        return Err(invalid_response("announce response is too short"));
+    }
+    let word = |i: usize| u32::from_be_bytes(body[i..i + 4].try_into().unwrap());
+
+    let peer_len = if ipv6 { 18 } else { 6 };
+    let peers = &body[12..];
+    if !peers.len().is_multiple_of(peer_len) {
+        return Err(invalid_response(format!(
+            "peer list of {} bytes is not a multiple of {}",
+            peers.len(),
+            peer_len
+        )));
+    }
+    let peers = peers
+        .chunks_exact(peer_len)
+        .map(|peer| {
+            let (ip, port) = peer.split_at(peer_len - 2);
+            let port = u16::from_be_bytes(port.try_into().unwrap());
+            match ip.len() {
+                4 => SocketAddr::from((<[u8; 4]>::try_from(ip).unwrap(), port)),
+                _ => SocketAddr::from((<[u8; 16]>::try_from(ip).unwrap(), port)),
+            }
+        })
+        .collect();
 
-struct AnnouncePayload {}
+    Ok(AnnounceResponse {
+        interval: word(0),
+        leechers: word(4),
+        seeders: word(8),
+        peers,
+    })
+}
 
 #[cfg(test)]
 mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
     use super::*;
+
+    /// A tracker on 127.0.0.1 that answers every packet it receives with
+    /// the packets `respond` returns for it.
+    async fn fake_tracker(
+        mut respond: impl FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    ) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                for response in respond(&buf[..len]) {
+                    socket.send_to(&response, from).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    fn response(action: u32, transaction_id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut packet = action.to_be_bytes().to_vec();
+        packet.extend(transaction_id);
+        packet.extend(body);
+        packet
+    }
+
+    const CONNECTION_ID: u64 = 0x1122334455667788;
+
+    /// Answers connect requests, and announces through `announce`.
+    fn tracker(
+        mut announce: impl FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    ) -> impl FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static {
+        move |packet| {
+            if packet[..8] == PROTOCOL_ID.to_be_bytes() {
+                assert_eq!(packet.len(), 16);
+                assert_eq!(packet[8..12], 0u32.to_be_bytes());
+                vec![response(0, &packet[12..16], &CONNECTION_ID.to_be_bytes())]
+            } else {
+                assert_eq!(packet[..8], CONNECTION_ID.to_be_bytes());
+                announce(packet)
+            }
+        }
+    }
+
+    fn request() -> AnnounceRequest {
+        AnnounceRequest {
+            info_hash: InfoHash([0xab; 20]),
+            peer_id: *b"-RS0001-abcdefghijkl",
+            downloaded: 1,
+            left: 2,
+            uploaded: 3,
+            event: AnnounceEvent::Started,
+            key: 0xdead,
+            num_want: None,
+            port: 6881,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tracker_announce() {
+        let addr = fake_tracker(tracker(|packet| {
+            assert_eq!(packet.len(), 98);
+            assert_eq!(packet[8..12], 1u32.to_be_bytes());
+            assert_eq!(packet[16..36], [0xab; 20]);
+            assert_eq!(&packet[36..56], b"-RS0001-abcdefghijkl");
+            assert_eq!(packet[56..64], 1u64.to_be_bytes());
+            assert_eq!(packet[64..72], 2u64.to_be_bytes());
+            assert_eq!(packet[72..80], 3u64.to_be_bytes());
+            assert_eq!(packet[80..84], 2u32.to_be_bytes());
+            assert_eq!(packet[88..92], 0xdeadu32.to_be_bytes());
+            assert_eq!(packet[92..96], (-1i32).to_be_bytes());
+            assert_eq!(packet[96..98], 6881u16.to_be_bytes());
+
+            let mut body = vec![];
+            for value in [1800u32, 5, 7] {
+                body.extend(value.to_be_bytes());
+            }
+            body.extend([10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0, 80]);
+            vec![response(1, &packet[12..16], &body)]
+        }))
+        .await;
+
+        let mut tracker = UdpTracker::new(addr).await.unwrap();
+        let response = tracker.announce(&request()).await.unwrap();
+        assert_eq!(
+            response,
+            AnnounceResponse {
+                interval: 1800,
+                leechers: 5,
+                seeders: 7,
+                peers: vec![
+                    "10.0.0.1:6881".parse().unwrap(),
+                    "192.168.1.2:80".parse().unwrap()
+                ],
+            }
+        );
+        assert_eq!(tracker.connection_id.unwrap().0, CONNECTION_ID);
+
+        let tracker = UdpTracker::from_url(&format!("udp://{}/announce", addr)).await;
+        assert!(tracker.is_ok());
+        for url in ["http://tracker.test/", "udp://tracker.test/announce"] {
+            let err = UdpTracker::from_url(url).await.err().unwrap();
+            assert!(matches!(&err, TrackerError::InvalidUrl(invalid) if invalid == url));
+            assert_eq!(err.to_string(), format!("not a UDP tracker URL: {}", url));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tracker_skips_other_transactions() {
+        let addr = fake_tracker(tracker(|packet| {
+            let mut other = packet[12..16].to_vec();
+            other[0] ^= 0xff;
+            vec![
+                // too short to be a response, then an answer to someone else
+                vec![0, 0, 0, 1],
+                response(3, &other, b"not for you"),
+                response(1, &packet[12..16], &[0; 12]),
+            ]
+        }))
+        .await;
+
+        let mut tracker = UdpTracker::new(addr).await.unwrap();
+        let response = tracker.announce(&request()).await.unwrap();
+        assert!(response.peers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tracker_errors() {
+        let addr = fake_tracker(tracker(|packet| {
+            let transaction_id = &packet[12..16];
+            // the first byte of the info-hash picks the answer
+            vec![match packet[16] {
+                0 => response(3, transaction_id, b"unknown torrent"),
+                1 => response(0, transaction_id, &[0; 12]),
+                _ => response(1, transaction_id, &[0; 15]),
+            }]
+        }))
+        .await;
+
+        let mut tracker = UdpTracker::new(addr).await.unwrap();
+        let cases = [
+            (0, "tracker error: unknown torrent"),
+            (1, "invalid tracker response: expected action 1 but got 0"),
+            (
+                2,
+                "invalid tracker response: peer list of 3 bytes is not a multiple of 6",
+            ),
+        ];
+        for (first_byte, message) in cases {
+            let mut request = request();
+            request.info_hash.0[0] = first_byte;
+            let err = tracker.announce(&request).await.unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    /// A tracker that ignores the first `drop` connect requests and the
+    /// first `drop` announces, and counts both.
+    async fn lossy_tracker(drop: usize) -> (SocketAddr, Arc<[AtomicUsize; 2]>) {
+        let counts = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let seen = counts.clone();
+        let mut respond = tracker(|packet| vec![response(1, &packet[12..16], &[0; 12])]);
+        let addr = fake_tracker(move |packet| {
+            let announce = usize::from(packet[..8] != PROTOCOL_ID.to_be_bytes());
+            if seen[announce].fetch_add(1, Ordering::SeqCst) < drop {
+                vec![]
+            } else {
+                respond(packet)
+            }
+        })
+        .await;
+        (addr, counts)
+    }
+
+    fn fast(connection_id_lifetime: Duration) -> UdpTrackerOptions {
+        UdpTrackerOptions {
+            base_timeout: Duration::from_millis(20),
+            max_retries: 2,
+            connection_id_lifetime,
+        }
+    }
+
+    fn counts(counts: &[AtomicUsize; 2]) -> [usize; 2] {
+        counts.each_ref().map(|count| count.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_retransmission() {
+        let (addr, seen) = lossy_tracker(1).await;
+        let mut options = fast(Duration::from_secs(60));
+        options.max_retries = 3;
+        let mut tracker = UdpTracker::with_options(addr, options).await.unwrap();
+
+        let start = Instant::now();
+        assert!(tracker.announce(&request()).await.is_ok());
+        assert_eq!(counts(&seen), [2, 2]);
+        // waited 20 ms for the first connect and 80 ms for the first
+        // announce, the third request overall
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (addr, seen) = lossy_tracker(usize::MAX).await;
+        let mut tracker = UdpTracker::with_options(addr, fast(Duration::from_secs(60)))
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        let err = tracker.announce(&request()).await.unwrap_err();
+        assert!(matches!(err, TrackerError::Timeout { attempts: 3 }));
+        assert_eq!(err.to_string(), "tracker did not respond to 3 attempts");
+        assert_eq!(counts(&seen), [3, 0]);
+        assert!(start.elapsed() >= Duration::from_millis(20 + 40 + 80));
+    }
+
+    #[tokio::test]
+    async fn test_connection_id_expiry() {
+        let (addr, seen) = lossy_tracker(0).await;
+        let mut tracker = UdpTracker::with_options(addr, fast(Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        tracker.announce(&request()).await.unwrap();
+        tracker.announce(&request()).await.unwrap();
+        assert_eq!(counts(&seen), [1, 2]);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        tracker.announce(&request()).await.unwrap();
+        assert_eq!(counts(&seen), [2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_one_retry_budget() {
+        let seen = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let counts_seen = seen.clone();
+        // answers connects but never announces
+        let mut respond = tracker(|_| vec![]);
+        let addr = fake_tracker(move |packet| {
+            let announce = usize::from(packet[..8] != PROTOCOL_ID.to_be_bytes());
+            counts_seen[announce].fetch_add(1, Ordering::SeqCst);
+            respond(packet)
+        })
+        .await;
+
+        // every announce retry needs a new connection id first
+        let mut options = fast(Duration::ZERO);
+        options.max_retries = 3;
+        let mut tracker = UdpTracker::with_options(addr, options).await.unwrap();
+        let err = tracker.announce(&request()).await.unwrap_err();
+        assert!(matches!(err, TrackerError::Timeout { attempts: 4 }));
+        assert_eq!(counts(&seen), [2, 2]);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let base = Duration::from_secs(15);
+        assert_eq!(backoff(base, 0), base);
+        assert_eq!(backoff(base, 3), Duration::from_secs(120));
+        assert_eq!(backoff(base, 8), Duration::from_secs(3840));
+        assert_eq!(backoff(base, 40), Duration::from_secs(3840));
+        assert_eq!(backoff(base, u64::MAX), Duration::from_secs(3840));
+        assert_eq!(backoff(Duration::MAX, 1), Duration::MAX);
+    }
+
     #[test]
-    fn test_tracker_connect() {
-        todo!();
+    fn test_parse_ipv6_peers() {
+        let mut body = vec![0; 12];
+        body.extend(Ipv6Addr::LOCALHOST.octets());
+        body.extend(6881u16.to_be_bytes());
+        let response = parse_announce_response(&body, true).unwrap();
+        assert_eq!(response.peers, ["[::1]:6881".parse().unwrap()]);
+
+        // an IPv4 peer where IPv6 ones are expected
+        assert!(parse_announce_response(&body[..18], true).is_err());
+        assert!(parse_announce_response(&[0; 11], false).is_err());
     }
 }